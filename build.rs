@@ -1,9 +1,31 @@
+extern crate cc;
 extern crate rustc_version;
 use rustc_version::{version_meta, Channel};
 
+use std::env;
+use std::process::Command;
+
 fn main() {
     // Set cfg flags depending on release channel
+    println!("cargo:rustc-check-cfg=cfg(nightly)");
     if let Channel::Nightly = version_meta().unwrap().channel {
         println!("cargo:rustc-cfg=nightly");
     }
+
+    // Compile the C++ functions of `src/ffi.cpp` against the LLVM found by
+    // llvm-sys
+    println!("cargo:rerun-if-changed=src/ffi.cpp");
+    let llvm_config = env::var("DEP_LLVM_14_CONFIG_PATH").unwrap_or_else(|_| "llvm-config".into());
+    let output = Command::new(&llvm_config)
+        .arg("--cxxflags")
+        .output()
+        .unwrap_or_else(|e| panic!("couldn't run {}: {}", llvm_config, e));
+    let cxxflags = String::from_utf8(output.stdout).unwrap();
+    let mut build = cc::Build::new();
+    // LLVM's headers aren't warning free
+    build.cpp(true).file("src/ffi.cpp").warnings(false);
+    for flag in cxxflags.split_whitespace() {
+        build.flag(flag);
+    }
+    build.compile("llvm-rs-ffi");
 }
@@ -1,10 +1,9 @@
 extern crate llvm;
 use llvm::*;
-use std::mem;
 
 fn main() {
     let context = Context::new();
-    let mut module = context.module_create_with_name("sum");
+    let module = context.module_create_with_name("sum");
     let mut builder = context.create_builder();
 
     let function_type = llvm::types::Function::new(
@@ -33,21 +32,21 @@ fn main() {
 
     module.dump();
 
+    // The builder borrows the module it built into, so it has to go before
+    // the module is handed to the execution engine
+    drop(builder);
+
     llvm::link_in_mcjit();
     llvm::initialize_native_target();
     llvm::initialize_native_asm_printer();
 
-    let ee = llvm::ExecutionEngine::create_for_module(&module).unwrap();
-    let addr = ee.get_function_address("fname").unwrap();
-
-    unsafe {
-        let f: extern "C" fn(u64, u64, u64) -> u64 = mem::transmute(addr);
+    let ee = llvm::ExecutionEngine::create_for_module(module).unwrap();
+    let f = ee.get_function::<extern "C" fn(u64, u64, u64) -> u64>("fname").unwrap();
 
-        let x: u64 = 1;
-        let y: u64 = 2;
-        let z: u64 = 3;
-        let res = f(x, y, z);
+    let x: u64 = 1;
+    let y: u64 = 2;
+    let z: u64 = 3;
+    let res = unsafe { f.call(x, y, z) };
 
-        println!("{} + {} + {} = {}", x, y, z, res);
-    }
+    println!("{} + {} + {} = {}", x, y, z, res);
 }
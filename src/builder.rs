@@ -1,241 +1,581 @@
-use llvm_sys::*;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
 use llvm_sys::prelude::*;
 use llvm_sys::core as llvm;
 
 use super::*;
+use value::*;
 
+// Most instructions can be constant folded by the builder, so unless an
+// instruction is guaranteed to be created, these return a `Value` rather than
+// an `Instruction`.
 macro_rules! build_op_str {
-    ($op_name: ident, $fn: path, $($argn: ident: $argv: path),*) => {
-        impl Builder {
-            pub fn $op_name(&mut self, $($argn: $argv),*, name: &str) -> LLVMValueRef {
+    ($op_name: ident -> $ret: ty, $fn: path, $($argn: ident: $argv: ty),*) => {
+        impl<'ctx> Builder<'ctx> {
+            pub fn $op_name(&mut self, $($argn: $argv),*, name: &str) -> &'ctx $ret {
                 let c_name = CString::new(name).unwrap();
-                unsafe {
-                    $fn(self.ptr, $($argn),*, c_name.as_ptr())
-                }
+                let value: &'ctx $ret = unsafe {
+                    $fn(self.ptr, $($argn.into()),*, c_name.as_ptr()).into()
+                };
+                self.apply_fast_math_flags(value);
+                value
             }
         }
     }
 }
 
 macro_rules! build_op {
-    ($op_name: ident, $fn: path, $($argn: ident: $argv: path),*) => {
-        impl Builder {
-            pub fn $op_name(&mut self, $($argn: $argv),*) -> LLVMValueRef {
+    ($op_name: ident -> $ret: ty, $fn: path, $($argn: ident: $argv: ty),*) => {
+        impl<'ctx> Builder<'ctx> {
+            pub fn $op_name(&mut self, $($argn: $argv),*) -> &'ctx $ret {
                 unsafe {
-                    $fn(self.ptr, $($argn),*)
+                    $fn(self.ptr, $($argn.into()),*).into()
                 }
             }
         }
     }
 }
 
+/// An instruction builder.
+///
+/// The instructions it builds borrow the module they're inserted into, so a
+/// builder that was positioned in a module keeps it borrowed until the
+/// builder is dropped, e.g. before the module is moved into an
+/// `ExecutionEngine`.
 #[derive(Debug)]
-pub struct Builder {
-    pub ptr: LLVMBuilderRef
+pub struct Builder<'ctx> {
+    pub ptr: LLVMBuilderRef,
+    fast_math_flags: FastMathFlags,
+    _marker: PhantomData<Cell<&'ctx ContextRef>>,
+}
+
+impl<'ctx> From<LLVMBuilderRef> for Builder<'ctx> {
+    fn from(ptr: LLVMBuilderRef) -> Self {
+        Builder {
+            ptr: ptr,
+            fast_math_flags: FastMathFlags::empty(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'ctx> From<Builder<'ctx>> for LLVMBuilderRef {
+    fn from(s: Builder<'ctx>) -> Self {
+        // The caller takes over ownership of the pointer
+        let ptr = s.ptr;
+        ::std::mem::forget(s);
+        ptr
+    }
 }
-impl_llvm_ref!(Builder, LLVMBuilderRef);
 
 // http://llvm.org/docs/doxygen/html/group__LLVMCCoreInstructionBuilder.html
-//TODO: Get/Set Volatile
-//TODO: Get/Set Ordering
 //TODO: Almost everything from LLVMBuildAdd and upwards
 
-build_op_str!(build_add, llvm::LLVMBuildAdd, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_sub, llvm::LLVMBuildSub, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_mul, llvm::LLVMBuildMul, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_fadd, llvm::LLVMBuildFAdd, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_fsub, llvm::LLVMBuildFSub, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_fmul, llvm::LLVMBuildFMul, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_fdiv, llvm::LLVMBuildFDiv, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_nswadd, llvm::LLVMBuildNSWAdd, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_nswsub, llvm::LLVMBuildNSWSub, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_nswmul, llvm::LLVMBuildNSWMul, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_nuwadd, llvm::LLVMBuildNUWAdd, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_nuwsub, llvm::LLVMBuildNUWSub, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_nuwmul, llvm::LLVMBuildNUWMul, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_udiv, llvm::LLVMBuildUDiv, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_sdiv, llvm::LLVMBuildSDiv, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_exact_sdiv, llvm::LLVMBuildExactSDiv, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_urem, llvm::LLVMBuildURem, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_srem, llvm::LLVMBuildSRem, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_frem, llvm::LLVMBuildFRem, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_shl, llvm::LLVMBuildShl, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_lshr, llvm::LLVMBuildLShr, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_ashr, llvm::LLVMBuildAShr, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-build_op_str!(build_and, llvm::LLVMBuildAnd, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_or, llvm::LLVMBuildOr, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_xor, llvm::LLVMBuildXor, lhs: LLVMValueRef, rhs: LLVMValueRef);
-
-// TODO: LLVMBuildBinOp
-
-build_op_str!(build_neg, llvm::LLVMBuildNeg, v: LLVMValueRef);
-build_op_str!(build_fneg, llvm::LLVMBuildFNeg, v: LLVMValueRef);
-build_op_str!(build_nswneg, llvm::LLVMBuildNSWNeg, v: LLVMValueRef);
-build_op_str!(build_nuwneg, llvm::LLVMBuildNUWNeg, v: LLVMValueRef);
-
-build_op_str!(build_not, llvm::LLVMBuildNot, v: LLVMValueRef);
-
-build_op_str!(build_malloc, llvm::LLVMBuildMalloc, typ: LLVMTypeRef);
-build_op_str!(build_array_malloc, llvm::LLVMBuildArrayMalloc, typ: LLVMTypeRef,
-                                                              val: LLVMValueRef);
-
-build_op_str!(build_alloca, llvm::LLVMBuildAlloca, ty: LLVMTypeRef);
-build_op_str!(build_array_alloca, llvm::LLVMBuildArrayAlloca, ty: LLVMTypeRef,
-                                                              val: LLVMValueRef);
-
-build_op!(build_free, llvm::LLVMBuildFree, pval: LLVMValueRef);
-build_op_str!(build_load, llvm::LLVMBuildLoad, ptr: LLVMValueRef);
-build_op!(build_store, llvm::LLVMBuildStore,  val: LLVMValueRef, pval: LLVMValueRef);
-
-build_op_str!(build_trunc, llvm::LLVMBuildTrunc, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_zext, llvm::LLVMBuildZExt, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_sext, llvm::LLVMBuildSExt, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_fp_to_ui, llvm::LLVMBuildFPToUI, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_fp_to_si, llvm::LLVMBuildFPToSI, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_ui_to_fp, llvm::LLVMBuildUIToFP, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_si_to_fp, llvm::LLVMBuildSIToFP, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_fp_trunc, llvm::LLVMBuildFPTrunc, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_fp_ext, llvm::LLVMBuildFPExt, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_ptr_to_int, llvm::LLVMBuildPtrToInt, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_int_to_ptr, llvm::LLVMBuildIntToPtr, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-
-build_op_str!(build_bit_cast, llvm::LLVMBuildBitCast, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_addr_space_cast, llvm::LLVMBuildAddrSpaceCast, val: LLVMValueRef,
-                                                                   dest_ty: LLVMTypeRef);
-build_op_str!(build_zext_or_bit_cast, llvm::LLVMBuildZExtOrBitCast, val: LLVMValueRef,
-                                                                    dest_ty: LLVMTypeRef);
-build_op_str!(build_sext_or_bit_cast, llvm::LLVMBuildSExtOrBitCast, val: LLVMValueRef,
-                                                                    dest_ty: LLVMTypeRef);
-build_op_str!(build_trunc_or_bit_cast, llvm::LLVMBuildTruncOrBitCast, val: LLVMValueRef,
-                                                                      dest_ty: LLVMTypeRef);
-
-// TODO: improve LLVMOpcode
-build_op_str!(build_cast, llvm::LLVMBuildCast, op: LLVMOpcode,
-                                               val: LLVMValueRef,
-                                               dest_ty: LLVMTypeRef);
-
-build_op_str!(build_pointer_cast, llvm::LLVMBuildPointerCast, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_int_cast, llvm::LLVMBuildIntCast, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-build_op_str!(build_fpcast, llvm::LLVMBuildFPCast, val: LLVMValueRef, dest_ty: LLVMTypeRef);
-
-
-build_op_str!(build_icmp, llvm::LLVMBuildICmp, op: LLVMIntPredicate,
-                                               lhs: LLVMValueRef,
-                                               rhs: LLVMValueRef);
-
-build_op_str!(build_fcmp, llvm::LLVMBuildFCmp, op: LLVMRealPredicate,
-                                               lhs: LLVMValueRef,
-                                               rhs: LLVMValueRef);
-
-build_op_str!(build_phi, llvm::LLVMBuildPhi, ty: LLVMTypeRef);
+build_op_str!(build_add -> Value, llvm::LLVMBuildAdd, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_sub -> Value, llvm::LLVMBuildSub, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_mul -> Value, llvm::LLVMBuildMul, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_fadd -> Value, llvm::LLVMBuildFAdd, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_fsub -> Value, llvm::LLVMBuildFSub, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_fmul -> Value, llvm::LLVMBuildFMul, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_fdiv -> Value, llvm::LLVMBuildFDiv, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_nswadd -> Value, llvm::LLVMBuildNSWAdd, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_nswsub -> Value, llvm::LLVMBuildNSWSub, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_nswmul -> Value, llvm::LLVMBuildNSWMul, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_nuwadd -> Value, llvm::LLVMBuildNUWAdd, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_nuwsub -> Value, llvm::LLVMBuildNUWSub, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_nuwmul -> Value, llvm::LLVMBuildNUWMul, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_udiv -> Value, llvm::LLVMBuildUDiv, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_sdiv -> Value, llvm::LLVMBuildSDiv, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_exact_udiv -> Value, llvm::LLVMBuildExactUDiv, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_exact_sdiv -> Value, llvm::LLVMBuildExactSDiv, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_urem -> Value, llvm::LLVMBuildURem, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_srem -> Value, llvm::LLVMBuildSRem, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_frem -> Value, llvm::LLVMBuildFRem, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_shl -> Value, llvm::LLVMBuildShl, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_lshr -> Value, llvm::LLVMBuildLShr, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_ashr -> Value, llvm::LLVMBuildAShr, lhs: &'ctx Value, rhs: &'ctx Value);
+
+build_op_str!(build_and -> Value, llvm::LLVMBuildAnd, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_or -> Value, llvm::LLVMBuildOr, lhs: &'ctx Value, rhs: &'ctx Value);
+build_op_str!(build_xor -> Value, llvm::LLVMBuildXor, lhs: &'ctx Value, rhs: &'ctx Value);
+
+impl<'ctx> Builder<'ctx> {
+    /// Builds the binary operator `op` with `flags`.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    ///
+    /// let context = Context::new();
+    /// let module = context.module_create_with_name("module");
+    /// let ty = types::Function::new(context.i32_type(), &[context.i32_type()], false);
+    /// let mut func = module.add_function(ty, "double");
+    /// let entry = context.append_basic_block(&mut func, "entry");
+    /// let mut builder = context.create_builder();
+    /// builder.position_at_end(entry);
+    /// let x = func.get_param(0).unwrap();
+    /// let y = builder.build_binop(BinaryOp::Shl, x, context.cons(1i32), &[], "y").unwrap();
+    /// let z = builder.build_binop(BinaryOp::Add, y, x,
+    ///                             &[BinaryOpFlag::NoSignedWrap, BinaryOpFlag::NoUnsignedWrap],
+    ///                             "z").unwrap();
+    /// builder.build_ret(z);
+    /// assert!(module.to_string().contains("%z = add nuw nsw i32 %y, %0"));
+    ///
+    /// assert!(builder.build_binop(BinaryOp::Or, x, y, &[BinaryOpFlag::Exact], "").is_err());
+    /// ```
+    ///
+    /// The flags are dropped if the operator is constant folded into a
+    /// constant expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnsupportedFlag` without building anything if `op` can't have
+    /// one of `flags`, see `BinaryOp::supports_flag`.
+    pub fn build_binop(&mut self,
+                       op: BinaryOp,
+                       lhs: &'ctx Value,
+                       rhs: &'ctx Value,
+                       flags: &[BinaryOpFlag],
+                       name: &str) -> enums::Result<&'ctx Value> {
+        if let Some(&flag) = flags.iter().find(|&&flag| !op.supports_flag(flag)) {
+            bail!(enums::ErrorKind::UnsupportedFlag(op, flag));
+        }
+        let c_name = CString::new(name).unwrap();
+        let value: &'ctx Value = unsafe {
+            llvm::LLVMBuildBinOp(self.ptr, op.into(), lhs.into(), rhs.into(), c_name.as_ptr()).into()
+        };
+        if let Some(operator) = value.try_as_binary_operator() {
+            for &flag in flags {
+                operator.set_flag(flag, true);
+            }
+        }
+        self.apply_fast_math_flags(value);
+        Ok(value)
+    }
+}
+
+build_op_str!(build_neg -> Value, llvm::LLVMBuildNeg, v: &'ctx Value);
+build_op_str!(build_fneg -> Value, llvm::LLVMBuildFNeg, v: &'ctx Value);
+build_op_str!(build_nswneg -> Value, llvm::LLVMBuildNSWNeg, v: &'ctx Value);
+build_op_str!(build_nuwneg -> Value, llvm::LLVMBuildNUWNeg, v: &'ctx Value);
+
+build_op_str!(build_not -> Value, llvm::LLVMBuildNot, v: &'ctx Value);
+
+impl<'ctx> Builder<'ctx> {
+    pub fn build_unop(&mut self, op: UnaryOp, v: &'ctx Value, name: &str) -> &'ctx Value {
+        match op {
+            UnaryOp::Neg => self.build_neg(v, name),
+            UnaryOp::FNeg => self.build_fneg(v, name),
+            UnaryOp::Not => self.build_not(v, name),
+        }
+    }
+}
+
+build_op_str!(build_malloc -> Instruction, llvm::LLVMBuildMalloc, typ: &'ctx Type);
+build_op_str!(build_array_malloc -> Instruction, llvm::LLVMBuildArrayMalloc, typ: &'ctx Type,
+                                                                             val: &'ctx Value);
+
+build_op_str!(build_alloca -> AllocaInst, llvm::LLVMBuildAlloca, ty: &'ctx Type);
+build_op_str!(build_array_alloca -> AllocaInst, llvm::LLVMBuildArrayAlloca, ty: &'ctx Type,
+                                                                            val: &'ctx Value);
+
+build_op!(build_free -> CallInst, llvm::LLVMBuildFree, pval: &'ctx Value);
+build_op_str!(build_load -> LoadInst, llvm::LLVMBuildLoad2, ty: &'ctx Type, ptr: &'ctx Value);
+build_op!(build_store -> StoreInst, llvm::LLVMBuildStore,  val: &'ctx Value, pval: &'ctx Value);
+
+build_op_str!(build_trunc -> Value, llvm::LLVMBuildTrunc, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_zext -> Value, llvm::LLVMBuildZExt, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_sext -> Value, llvm::LLVMBuildSExt, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_fp_to_ui -> Value, llvm::LLVMBuildFPToUI, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_fp_to_si -> Value, llvm::LLVMBuildFPToSI, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_ui_to_fp -> Value, llvm::LLVMBuildUIToFP, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_si_to_fp -> Value, llvm::LLVMBuildSIToFP, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_fp_trunc -> Value, llvm::LLVMBuildFPTrunc, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_fp_ext -> Value, llvm::LLVMBuildFPExt, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_ptr_to_int -> Value, llvm::LLVMBuildPtrToInt, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_int_to_ptr -> Value, llvm::LLVMBuildIntToPtr, val: &'ctx Value, dest_ty: &'ctx Type);
+
+build_op_str!(build_bit_cast -> Value, llvm::LLVMBuildBitCast, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_addr_space_cast -> Value, llvm::LLVMBuildAddrSpaceCast, val: &'ctx Value,
+                                                                            dest_ty: &'ctx Type);
+build_op_str!(build_zext_or_bit_cast -> Value, llvm::LLVMBuildZExtOrBitCast, val: &'ctx Value,
+                                                                             dest_ty: &'ctx Type);
+build_op_str!(build_sext_or_bit_cast -> Value, llvm::LLVMBuildSExtOrBitCast, val: &'ctx Value,
+                                                                             dest_ty: &'ctx Type);
+build_op_str!(build_trunc_or_bit_cast -> Value, llvm::LLVMBuildTruncOrBitCast, val: &'ctx Value,
+                                                                               dest_ty: &'ctx Type);
+
+build_op_str!(build_cast -> Value, llvm::LLVMBuildCast, op: Opcode,
+                                                        val: &'ctx Value,
+                                                        dest_ty: &'ctx Type);
+
+build_op_str!(build_pointer_cast -> Value, llvm::LLVMBuildPointerCast, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_int_cast -> Value, llvm::LLVMBuildIntCast, val: &'ctx Value, dest_ty: &'ctx Type);
+build_op_str!(build_fpcast -> Value, llvm::LLVMBuildFPCast, val: &'ctx Value, dest_ty: &'ctx Type);
+
+
+build_op_str!(build_icmp -> Value, llvm::LLVMBuildICmp, op: IntPredicate,
+                                                        lhs: &'ctx Value,
+                                                        rhs: &'ctx Value);
+
+build_op_str!(build_fcmp -> Value, llvm::LLVMBuildFCmp, op: RealPredicate,
+                                                        lhs: &'ctx Value,
+                                                        rhs: &'ctx Value);
+
+build_op_str!(build_phi -> PhiNode, llvm::LLVMBuildPhi, ty: &'ctx Type);
 //build_call is manually defined in impl Builder
-build_op_str!(build_select, llvm::LLVMBuildSelect, i: LLVMValueRef,
-                                                   the: LLVMValueRef,
-                                                   els: LLVMValueRef);
+build_op_str!(build_select -> Value, llvm::LLVMBuildSelect, i: &'ctx Value,
+                                                            the: &'ctx Value,
+                                                            els: &'ctx Value);
 
-build_op_str!(build_vaarg, llvm::LLVMBuildVAArg, list: LLVMValueRef, ty: LLVMTypeRef);
+build_op_str!(build_vaarg -> Instruction, llvm::LLVMBuildVAArg, list: &'ctx Value, ty: &'ctx Type);
 
 
-build_op_str!(build_extract_element, llvm::LLVMBuildExtractElement, vec_val: LLVMValueRef,
-                                                                    index: LLVMValueRef);
-build_op_str!(build_insert_element, llvm::LLVMBuildInsertElement, vec_val: LLVMValueRef,
-                                                                  eltval: LLVMValueRef,
-                                                                  index: LLVMValueRef);
-build_op_str!(build_shuffle_vector, llvm::LLVMBuildShuffleVector, v1: LLVMValueRef,
-                                                                  v2: LLVMValueRef,
-                                                                  mask: LLVMValueRef);
+build_op_str!(build_extract_element -> Value, llvm::LLVMBuildExtractElement, vec_val: &'ctx Value,
+                                                                             index: &'ctx Value);
+build_op_str!(build_insert_element -> Value, llvm::LLVMBuildInsertElement, vec_val: &'ctx Value,
+                                                                           eltval: &'ctx Value,
+                                                                           index: &'ctx Value);
+build_op_str!(build_shuffle_vector -> Value, llvm::LLVMBuildShuffleVector, v1: &'ctx Value,
+                                                                           v2: &'ctx Value,
+                                                                           mask: &'ctx Value);
 
 // TODO: Both these types use unsigned, change this to libc::unsigned
-build_op_str!(build_extract_value, llvm::LLVMBuildExtractValue, aggval: LLVMValueRef,
-                                                                index: u32);
-build_op_str!(build_insert_value, llvm::LLVMBuildInsertValue, aggval: LLVMValueRef,
-                                                              eltval: LLVMValueRef,
-                                                              index: u32);
+build_op_str!(build_extract_value -> Value, llvm::LLVMBuildExtractValue, aggval: &'ctx Value,
+                                                                         index: u32);
+build_op_str!(build_insert_value -> Value, llvm::LLVMBuildInsertValue, aggval: &'ctx Value,
+                                                                       eltval: &'ctx Value,
+                                                                       index: u32);
+
+build_op!(build_atomic_rmw -> AtomicRMWInst, llvm::LLVMBuildAtomicRMW, op: AtomicRMWBinOp,
+                                                                       ptr: &'ctx Value,
+                                                                       val: &'ctx Value,
+                                                                       ordering: AtomicOrdering,
+                                                                       single_thread: bool);
+// Returns a `{ <ty>, i1 }` pair of the loaded value and whether it was equal
+// to `cmp` (and so was replaced by `new`), see `build_extract_value`.
+build_op!(build_atomic_cmpxchg -> AtomicCmpXchgInst, llvm::LLVMBuildAtomicCmpXchg,
+          ptr: &'ctx Value,
+          cmp: &'ctx Value,
+          new: &'ctx Value,
+          success_ordering: AtomicOrdering,
+          failure_ordering: AtomicOrdering,
+          single_thread: bool);
+
+build_op_str!(build_is_null -> Value, llvm::LLVMBuildIsNull, val: &'ctx Value);
+build_op_str!(build_is_not_null -> Value, llvm::LLVMBuildIsNotNull, val: &'ctx Value);
+build_op_str!(build_ptr_diff -> Value, llvm::LLVMBuildPtrDiff2, elem_ty: &'ctx Type, lhs: &'ctx Value,
+                                                                  rhs: &'ctx Value);
+build_op_str!(build_fence -> Instruction, llvm::LLVMBuildFence, ordering: AtomicOrdering,
+                                                                singlethread: bool);
+
+
+
+
+build_op!(build_ret -> ReturnInst, llvm::LLVMBuildRet, ret_val: &'ctx Value);
+build_op!(build_ret_void -> ReturnInst, llvm::LLVMBuildRetVoid,); // TODO: Fix the trailing comma
+build_op!(build_br -> BranchInst, llvm::LLVMBuildBr, dest: &'ctx BasicBlock);
+
+build_op!(build_cond_br -> BranchInst, llvm::LLVMBuildCondBr, cond: &'ctx Value,
+                                                              then: &'ctx BasicBlock,
+                                                              else_: &'ctx BasicBlock);
+build_op!(build_unreachable -> UnreachableInst, llvm::LLVMBuildUnreachable,);
+
+build_op!(build_resume -> ResumeInst, llvm::LLVMBuildResume, exception: &'ctx Value);
+build_op!(build_catch_ret -> CatchReturnInst, llvm::LLVMBuildCatchRet, catch_pad: &'ctx CatchPadInst,
+                                                                      dest: &'ctx BasicBlock);
 
-// TODO: LLVMBuildAtomicRMW
 
-build_op_str!(build_is_null, llvm::LLVMBuildIsNull, val: LLVMValueRef);
-build_op_str!(build_is_not_null, llvm::LLVMBuildIsNotNull, val: LLVMValueRef);
-build_op_str!(build_ptr_diff, llvm::LLVMBuildPtrDiff, lhs: LLVMValueRef, rhs: LLVMValueRef);
-build_op_str!(build_fence, llvm::LLVMBuildFence, ordering: LLVMAtomicOrdering,
-                                                 singlethread: LLVMBool);
 
 
+// Fast math flags are set through `ffi`, see `Instruction::fast_math_flags`
+impl<'ctx> Builder<'ctx> {
+    /// Returns the fast math flags given to the instructions built that can
+    /// have them.
+    pub fn fast_math_flags(&self) -> FastMathFlags {
+        self.fast_math_flags
+    }
 
+    /// Sets the fast math flags given to every instruction built from now on
+    /// that can have them, see `Instruction::supports_fast_math_flags`.
+    pub fn set_fast_math_flags(&mut self, flags: FastMathFlags) {
+        self.fast_math_flags = flags;
+    }
 
-build_op!(build_ret, llvm::LLVMBuildRet, ret_val: LLVMValueRef);
-build_op!(build_ret_void, llvm::LLVMBuildRetVoid,); // TODO: Fix the trailing comma
-build_op!(build_br, llvm::LLVMBuildBr, dest: LLVMBasicBlockRef);
+    fn apply_fast_math_flags(&self, value: &Value) {
+        if self.fast_math_flags.is_empty() {
+            return;
+        }
+        if let Some(instruction) = value.try_as_instruction() {
+            if instruction.supports_fast_math_flags() {
+                instruction.set_fast_math_flags(self.fast_math_flags);
+            }
+        }
+    }
+}
 
-build_op!(build_cond_br, llvm::LLVMBuildCondBr, cond: LLVMValueRef,
-                                                then: LLVMBasicBlockRef,
-                                                else_: LLVMBasicBlockRef);
+impl<'ctx> Builder<'ctx> {
+    pub fn position_at_end(&mut self, basic_block: &'ctx BasicBlock) {
+        unsafe {
+            llvm::LLVMPositionBuilderAtEnd(self.ptr, basic_block.into());
+        }
+    }
 
+    /// Positions the builder to insert instructions right before
+    /// `instruction`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instruction` isn't in a basic block.
+    pub fn position_before(&mut self, instruction: &'ctx Instruction) {
+        assert!(instruction.parent().is_some(), "instruction is not in a basic block");
+        unsafe {
+            llvm::LLVMPositionBuilderBefore(self.ptr, instruction.into());
+        }
+    }
 
+    /// Positions the builder before `instruction`, which must be in
+    /// `basic_block`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instruction` isn't in `basic_block`.
+    pub fn position_at(&mut self, basic_block: &'ctx BasicBlock, instruction: &'ctx Instruction) {
+        assert!(instruction.parent() == Some(basic_block),
+                "instruction is not in the given basic block");
+        unsafe {
+            llvm::LLVMPositionBuilder(self.ptr, basic_block.into(), instruction.into());
+        }
+    }
+
+    /// Returns the basic block instructions are inserted into, or `None` if
+    /// the builder isn't positioned.
+    pub fn insertion_block(&self) -> Option<&'ctx BasicBlock> {
+        let block = unsafe { llvm::LLVMGetInsertBlock(self.ptr) };
+        if block.is_null() {
+            None
+        } else {
+            Some(block.into())
+        }
+    }
 
+    /// Unpositions the builder, which must be positioned again before
+    /// building instructions.
+    pub fn clear_insertion_position(&mut self) {
+        unsafe {
+            llvm::LLVMClearInsertionPosition(self.ptr);
+        }
+    }
 
-impl Builder {
-    pub fn position_at_end(&mut self, basic_block: LLVMBasicBlockRef) {
+    /// Inserts `instruction`, which was removed from its basic block with
+    /// `Instruction::remove_from_parent`, at the position of the builder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the builder isn't positioned.
+    pub fn insert_into_builder(&mut self, instruction: DetachedInstruction<'ctx>) -> &'ctx Instruction {
+        assert!(self.insertion_block().is_some(), "builder is not positioned");
+        let instruction = instruction.into_instruction();
         unsafe {
-            llvm::LLVMPositionBuilderAtEnd(self.ptr, basic_block);
+            llvm::LLVMInsertIntoBuilder(self.ptr, instruction.into());
+        }
+        instruction
+    }
+
+    /// Builds a `switch` on `value`, which jumps to the block of the case
+    /// matching `value`, or to `default` if there is none. More cases can be
+    /// added with `SwitchInst::add_case`.
+    pub fn build_switch(&mut self,
+                        value: &'ctx Value,
+                        default: &'ctx BasicBlock,
+                        cases: &[(&'ctx ConstantInt, &'ctx BasicBlock)]) -> &'ctx SwitchInst {
+        let switch: &SwitchInst = unsafe {
+            llvm::LLVMBuildSwitch(self.ptr, value.into(), default.into(), cases.len() as u32).into()
+        };
+        for &(on, dest) in cases {
+            switch.add_case(on, dest);
         }
+        switch
     }
 
-    pub fn build_call(&mut self, func: Function, mut args: Vec<LLVMValueRef>,
-                      name: &str) -> LLVMValueRef {
+    /// Builds an `indirectbr` to `address`, which must be the address of one
+    /// of `destinations`, see `BasicBlock::address`.
+    pub fn build_indirect_br(&mut self,
+                             address: &'ctx Value,
+                             destinations: &[&'ctx BasicBlock]) -> &'ctx IndirectBrInst {
+        let indirect_br: &IndirectBrInst = unsafe {
+            llvm::LLVMBuildIndirectBr(self.ptr, address.into(), destinations.len() as u32).into()
+        };
+        for &dest in destinations {
+            indirect_br.add_destination(dest);
+        }
+        indirect_br
+    }
+
+    /// Builds an `invoke` of `func`, which continues at `then` if `func`
+    /// returns, and at `catch` if it throws.
+    pub fn build_invoke(&mut self, func: Function<'ctx>, args: &[&'ctx Value],
+                        then: &'ctx BasicBlock, catch: &'ctx BasicBlock,
+                        name: &str) -> &'ctx InvokeInst {
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMBuildCall(
+            llvm::LLVMBuildInvoke2(
                 self.ptr,
+                llvm::LLVMGlobalGetValueType(func.ptr),
                 func.ptr,
-                args.as_mut_ptr(),
+                args.as_ptr() as *mut LLVMValueRef,
                 args.len() as u32,
+                then.into(),
+                catch.into(),
                 c_name.as_ptr()
-            )
+            ).into()
+        }
+    }
+
+    /// Builds a `landingpad` returning a value of type `ty`, usually
+    /// `{ i8*, i32 }`, with the given `catch` or `filter` clauses. The
+    /// personality is that of the enclosing function, see
+    /// `Function::set_personality`.
+    pub fn build_landing_pad(&mut self, ty: &'ctx Type, clauses: &[&'ctx Constant],
+                             cleanup: bool, name: &str) -> &'ctx LandingPadInst {
+        let c_name = CString::new(name).unwrap();
+        let landing_pad: &LandingPadInst = unsafe {
+            llvm::LLVMBuildLandingPad(self.ptr, ty.into(), ptr::null_mut(),
+                                      clauses.len() as u32, c_name.as_ptr()).into()
+        };
+        for &clause in clauses {
+            landing_pad.add_clause(clause);
+        }
+        landing_pad.set_cleanup(cleanup);
+        landing_pad
+    }
+
+    /// Builds a `catchswitch` trying each of `handlers`, and unwinding to
+    /// `unwind`, or to the caller if it is `None`, if none of them catches
+    /// the exception. `parent_pad` is the enclosing funclet pad, or `None`
+    /// at the top level.
+    pub fn build_catch_switch(&mut self, parent_pad: Option<&'ctx Value>,
+                              unwind: Option<&'ctx BasicBlock>,
+                              handlers: &[&'ctx BasicBlock],
+                              name: &str) -> &'ctx CatchSwitchInst {
+        let c_name = CString::new(name).unwrap();
+        let catch_switch: &CatchSwitchInst = unsafe {
+            llvm::LLVMBuildCatchSwitch(self.ptr, parent_pad.map_or(ptr::null_mut(), Into::into),
+                                       unwind.map_or(ptr::null_mut(), Into::into),
+                                       handlers.len() as u32, c_name.as_ptr()).into()
+        };
+        for &handler in handlers {
+            catch_switch.add_handler(handler);
+        }
+        catch_switch
+    }
+
+    /// Builds a `catchpad` handler of `catch_switch`. `args` are passed to
+    /// the personality function to decide if the exception is caught.
+    pub fn build_catch_pad(&mut self, catch_switch: &'ctx CatchSwitchInst,
+                           args: &[&'ctx Value], name: &str) -> &'ctx CatchPadInst {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            llvm::LLVMBuildCatchPad(self.ptr, catch_switch.into(),
+                                    args.as_ptr() as *mut LLVMValueRef,
+                                    args.len() as u32, c_name.as_ptr()).into()
+        }
+    }
+
+    /// Builds a `cleanuppad` inside `parent_pad`, or at the top level if it
+    /// is `None`.
+    pub fn build_cleanup_pad(&mut self, parent_pad: Option<&'ctx Value>,
+                             args: &[&'ctx Value], name: &str) -> &'ctx CleanupPadInst {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            llvm::LLVMBuildCleanupPad(self.ptr, parent_pad.map_or(ptr::null_mut(), Into::into),
+                                      args.as_ptr() as *mut LLVMValueRef,
+                                      args.len() as u32, c_name.as_ptr()).into()
         }
     }
-    pub fn build_global_string(&self, s: &str, name: &str) -> LLVMValueRef {
+
+    /// Builds a `cleanupret` leaving `cleanup_pad` and unwinding to
+    /// `unwind`, or to the caller if it is `None`.
+    pub fn build_cleanup_ret(&mut self, cleanup_pad: &'ctx CleanupPadInst,
+                             unwind: Option<&'ctx BasicBlock>) -> &'ctx CleanupReturnInst {
+        unsafe {
+            llvm::LLVMBuildCleanupRet(self.ptr, cleanup_pad.into(),
+                                      unwind.map_or(ptr::null_mut(), Into::into)).into()
+        }
+    }
+
+    /// Builds a `call` of `func`. Its calling convention, tail call kind and
+    /// attributes can be set on the returned call site.
+    pub fn build_call(&mut self, func: Function<'ctx>, args: &[&'ctx Value],
+                      name: &str) -> &'ctx CallInst {
+        let fn_type = unsafe { llvm::LLVMGlobalGetValueType(func.ptr).into() };
+        self.build_indirect_call(fn_type, func.as_value(), args, name)
+    }
+
+    /// Builds a `call` through `callee`, a pointer to a function of type
+    /// `fn_type`.
+    pub fn build_indirect_call(&mut self, fn_type: &'ctx types::Function, callee: &'ctx Value,
+                               args: &[&'ctx Value], name: &str) -> &'ctx CallInst {
+        let c_name = CString::new(name).unwrap();
+        let call: &CallInst = unsafe {
+            llvm::LLVMBuildCall2(
+                self.ptr,
+                fn_type.into(),
+                callee.into(),
+                args.as_ptr() as *mut LLVMValueRef,
+                args.len() as u32,
+                c_name.as_ptr()
+            ).into()
+        };
+        self.apply_fast_math_flags(call);
+        call
+    }
+
+    pub fn build_global_string(&self, s: &str, name: &str) -> &'ctx GlobalVariable {
         let c_s = CString::new(s).unwrap();
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMBuildGlobalString(self.ptr, c_s.as_ptr(), c_name.as_ptr())
+            llvm::LLVMBuildGlobalString(self.ptr, c_s.as_ptr(), c_name.as_ptr()).into()
         }
     }
 
-    pub fn build_global_string_ptr(&self, s: &str, name: &str) -> LLVMValueRef {
+    pub fn build_global_string_ptr(&self, s: &str, name: &str) -> &'ctx Constant {
         let c_s = CString::new(s).unwrap();
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMBuildGlobalStringPtr(self.ptr, c_s.as_ptr(), c_name.as_ptr())
+            llvm::LLVMBuildGlobalStringPtr(self.ptr, c_s.as_ptr(), c_name.as_ptr()).into()
         }
     }
 
-    pub fn build_in_bounds_gep(&self, ptr: LLVMValueRef, mut indices: Vec<LLVMValueRef>,
-                               name: &str) -> LLVMValueRef {
+    pub fn build_in_bounds_gep(&self, ty: &'ctx Type, ptr: &'ctx Value, indices: Vec<&'ctx Value>,
+                               name: &str) -> &'ctx Value {
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMBuildInBoundsGEP(self.ptr, ptr, indices.as_mut_ptr(),
-                                       indices.len() as u32, c_name.as_ptr())
+            llvm::LLVMBuildInBoundsGEP2(self.ptr, ty.into(), ptr.into(),
+                                        indices.as_ptr() as *mut LLVMValueRef,
+                                        indices.len() as u32, c_name.as_ptr()).into()
         }
     }
-    pub fn build_gep(&self, ptr: LLVMValueRef, mut indices: Vec<LLVMValueRef>,
-                               name: &str) -> LLVMValueRef {
+    pub fn build_gep(&self, ty: &'ctx Type, ptr: &'ctx Value, indices: Vec<&'ctx Value>,
+                     name: &str) -> &'ctx Value {
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMBuildGEP(self.ptr, ptr, indices.as_mut_ptr(),
-                                       indices.len() as u32, c_name.as_ptr())
+            llvm::LLVMBuildGEP2(self.ptr, ty.into(), ptr.into(),
+                                indices.as_ptr() as *mut LLVMValueRef,
+                                indices.len() as u32, c_name.as_ptr()).into()
         }
     }
 }
 
-impl Drop for Builder {
+impl<'ctx> Drop for Builder<'ctx> {
     fn drop(&mut self) {
         unsafe {
             llvm::LLVMDisposeBuilder(self.ptr);
@@ -243,3 +583,459 @@ impl Drop for Builder {
     }
 }
 
+/// Saves the position of a `Builder`, and restores it when dropped. The
+/// builder can be used and moved through the guard in the meantime:
+///
+/// ```rust
+/// # use llvm::*;
+/// # let context = Context::new();
+/// # let module = context.module_create_with_name("test");
+/// # let func_ty = types::Function::new(context.void_type(), &[], false);
+/// # let mut func = module.add_function(func_ty, "func");
+/// let entry = context.append_basic_block(&mut func, "entry");
+/// let body = context.append_basic_block(&mut func, "body");
+/// let mut builder = context.create_builder();
+/// builder.position_at_end(body);
+/// {
+///     let mut guard = InsertPointGuard::new(&mut builder);
+///     guard.position_at_end(entry);
+///     guard.build_alloca(context.i32_type(), "local");
+/// }
+/// assert_eq!(builder.insertion_block(), Some(body));
+/// ```
+///
+/// If the builder was positioned before an instruction that's no longer in
+/// the same block when the guard is dropped, the builder is positioned at the
+/// end of that block instead.
+#[derive(Debug)]
+pub struct InsertPointGuard<'b, 'ctx: 'b> {
+    builder: &'b mut Builder<'ctx>,
+    // The block the builder inserted into and the instruction it inserted
+    // before, which are null when it was unpositioned or at the end of the
+    // block
+    block: LLVMBasicBlockRef,
+    instruction: LLVMValueRef,
+}
+
+impl<'b, 'ctx> InsertPointGuard<'b, 'ctx> {
+    pub fn new(builder: &'b mut Builder<'ctx>) -> Self {
+        // The C API only tells the block a builder inserts into, so the
+        // instruction it inserts before is found by inserting a placeholder
+        unsafe {
+            let block = llvm::LLVMGetInsertBlock(builder.ptr);
+            let mut instruction = ptr::null_mut();
+            if !block.is_null() {
+                let placeholder = llvm::LLVMBuildUnreachable(builder.ptr);
+                instruction = llvm::LLVMGetNextInstruction(placeholder);
+                llvm::LLVMInstructionEraseFromParent(placeholder);
+            }
+            InsertPointGuard {
+                builder: builder,
+                block: block,
+                instruction: instruction,
+            }
+        }
+    }
+}
+
+impl<'b, 'ctx> Deref for InsertPointGuard<'b, 'ctx> {
+    type Target = Builder<'ctx>;
+
+    fn deref(&self) -> &Builder<'ctx> {
+        self.builder
+    }
+}
+
+impl<'b, 'ctx> DerefMut for InsertPointGuard<'b, 'ctx> {
+    fn deref_mut(&mut self) -> &mut Builder<'ctx> {
+        self.builder
+    }
+}
+
+impl<'b, 'ctx> Drop for InsertPointGuard<'b, 'ctx> {
+    fn drop(&mut self) {
+        let ptr = self.builder.ptr;
+        unsafe {
+            if self.block.is_null() {
+                llvm::LLVMClearInsertionPosition(ptr);
+            } else if !self.instruction.is_null() &&
+                      llvm::LLVMGetInstructionParent(self.instruction) == self.block {
+                llvm::LLVMPositionBuilder(ptr, self.block, self.instruction);
+            } else {
+                llvm::LLVMPositionBuilderAtEnd(ptr, self.block);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_hoist_instructions_to_the_entry_block() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+        assert!(builder.insertion_block().is_none());
+
+        let func_ty = ::types::Function::new(context.i32_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        let body = context.append_basic_block(&mut func, "body");
+
+        builder.position_at_end(entry);
+        let br = builder.build_br(body);
+        builder.position_at_end(body);
+        let loaded = {
+            let mut guard = InsertPointGuard::new(&mut builder);
+            guard.position_before(br);
+            let slot = guard.build_alloca(context.i32_type(), "slot");
+            guard.build_store(context.cons(1i32), slot);
+            guard.clear_insertion_position();
+            slot
+        };
+        assert_eq!(builder.insertion_block(), Some(body));
+        let value = builder.build_load(context.i32_type(), loaded, "value");
+
+        // Move the load from `body` to the end of `entry`, before the branch
+        let detached = unsafe { value.remove_from_parent() };
+        assert!(detached.parent().is_none());
+        let value = {
+            let mut guard = InsertPointGuard::new(&mut builder);
+            guard.position_at(entry, br);
+            guard.insert_into_builder(detached)
+        };
+        assert_eq!(value.parent(), Some(entry));
+        builder.build_ret(value);
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("entry:\n  %slot = alloca i32, align 4\n  store i32 1, i32* %slot, align 4\n  \
+                             %0 = load i32, i32* %slot, align 4\n  br label %body\n\n\
+                             body:                                             ; preds = %entry\n  ret i32 %0"),
+                "{}", ir);
+    }
+
+    #[test]
+    fn erased_instructions_leave_undef_behind() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i32 @f(i32 %x) {
+              %y = add i32 %x, 1
+              ret i32 %y
+            }
+        ").unwrap();
+        let function = module.get_named_function("f").unwrap();
+        let entry = function.entry_basic_block().unwrap();
+        let add = entry.instructions().next().unwrap();
+
+        // `add` isn't used afterwards
+        unsafe { add.remove_from_parent() }.erase();
+        assert_eq!(entry.instructions().count(), 1);
+        assert!(module.verify().is_ok());
+        assert!(module.to_string().contains("ret i32 undef"));
+    }
+
+    #[test]
+    fn guards_restore_positions_set_through_raw_pointers() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define void @f() {
+              ret void
+            }
+        ").unwrap();
+        let function = module.get_named_function("f").unwrap();
+        let entry = function.entry_basic_block().unwrap();
+        let ret = entry.instructions().next().unwrap();
+        let mut builder = context.create_builder();
+        unsafe { llvm::LLVMPositionBuilderBefore(builder.ptr, ret.into()) };
+        {
+            let mut guard = InsertPointGuard::new(&mut builder);
+            guard.clear_insertion_position();
+        }
+        builder.build_fence(AtomicOrdering::SequentiallyConsistent, false, "");
+
+        assert!(module.verify().is_ok());
+        assert!(module.to_string().contains("fence seq_cst\n  ret void"));
+    }
+
+    #[test]
+    fn guards_fall_back_to_the_end_of_the_block() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i32 @f(i32 %x) {
+            entry:
+              %y = add i32 %x, 1
+              br label %exit
+
+            exit:
+              ret i32 %y
+            }
+        ").unwrap();
+        let function = module.get_named_function("f").unwrap();
+        let entry = function.entry_basic_block().unwrap();
+        let exit: &BasicBlock = unsafe { llvm::LLVMGetNextBasicBlock(entry.into()).into() };
+        let add = entry.instructions().next().unwrap();
+        let mut builder = context.create_builder();
+        builder.position_before(add);
+        {
+            // Move `add` to `exit`, which the builder doesn't follow
+            let mut guard = InsertPointGuard::new(&mut builder);
+            let detached = unsafe { add.remove_from_parent() };
+            guard.position_before(exit.terminator().unwrap());
+            guard.insert_into_builder(detached);
+        }
+        assert_eq!(builder.insertion_block(), Some(entry));
+        builder.build_unreachable();
+        assert_eq!(entry.instructions().last().unwrap().opcode(), Opcode::Unreachable);
+    }
+
+    #[test]
+    #[should_panic(expected = "instruction is not in a basic block")]
+    fn builders_cant_be_positioned_before_detached_instructions() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define void @f() {
+              ret void
+            }
+        ").unwrap();
+        let function = module.get_named_function("f").unwrap();
+        let ret = function.entry_basic_block().unwrap().instructions().next().unwrap();
+        let mut builder = context.create_builder();
+        let _detached = unsafe { ret.remove_from_parent() };
+        builder.position_before(ret);
+    }
+
+    #[test]
+    #[should_panic(expected = "instruction is not in the given basic block")]
+    fn builders_are_positioned_in_the_block_of_the_instruction() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        let exit = context.append_basic_block(&mut func, "exit");
+
+        builder.position_at_end(exit);
+        let ret = builder.build_ret_void();
+        builder.position_at(entry, ret);
+    }
+
+    #[test]
+    fn can_build_binops_with_flags() {
+        use BinaryOpFlag::*;
+
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let i32_type = context.i32_type();
+        let func_ty = ::types::Function::new(i32_type, &[i32_type, i32_type], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+        let (x, y) = (func.get_param(0).unwrap(), func.get_param(1).unwrap());
+
+        let sum = builder.build_binop(BinaryOp::Add, x, y, &[NoSignedWrap], "sum").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert_eq!(sum.op(), BinaryOp::Add);
+        assert!(sum.has_no_signed_wrap());
+        assert!(!sum.has_no_unsigned_wrap() && !sum.is_exact());
+
+        let product = builder.build_binop(BinaryOp::Mul, sum, y, &[NoUnsignedWrap, NoSignedWrap],
+                                          "product").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert!(product.has_no_unsigned_wrap() && product.has_no_signed_wrap());
+        product.set_no_signed_wrap(false);
+        assert!(product.has_no_unsigned_wrap() && !product.has_no_signed_wrap());
+
+        let shifted = builder.build_binop(BinaryOp::Shl, product, x, &[NoSignedWrap, NoUnsignedWrap],
+                                          "shifted").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert!(shifted.has_flag(NoSignedWrap) && shifted.has_flag(NoUnsignedWrap));
+
+        let quotient = builder.build_binop(BinaryOp::SDiv, shifted, y, &[Exact], "quotient").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert!(quotient.is_exact());
+        assert!(!quotient.has_no_signed_wrap());
+
+        let halved = builder.build_binop(BinaryOp::AShr, quotient, context.cons(1i32), &[],
+                                         "halved").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert!(!halved.is_exact());
+        halved.set_flag(Exact, true);
+        assert!(halved.is_exact());
+
+        let difference = builder.build_binop(BinaryOp::Sub, halved, x, &[], "nsw = exact").unwrap()
+            .try_as_binary_operator().unwrap();
+        assert!(!difference.has_no_signed_wrap() && !difference.has_no_unsigned_wrap());
+
+        let negated = builder.build_unop(UnaryOp::Neg, difference, "negated");
+        let result = builder.build_unop(UnaryOp::Not, negated, "result");
+        builder.build_ret(result);
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("%sum = add nsw i32 %0, %1"), "{}", ir);
+        assert!(ir.contains("%product = mul nuw i32 %sum, %1"), "{}", ir);
+        assert!(ir.contains("%shifted = shl nuw nsw i32 %product, %0"), "{}", ir);
+        assert!(ir.contains("%quotient = sdiv exact i32 %shifted, %1"), "{}", ir);
+        assert!(ir.contains("%halved = ashr exact i32 %quotient, 1"), "{}", ir);
+        assert!(ir.contains("%\"nsw = exact\" = sub i32 %halved, %0"), "{}", ir);
+        assert!(ir.contains("%negated = sub i32 0, %\"nsw = exact\""), "{}", ir);
+        assert!(ir.contains("%result = xor i32 %negated, -1"), "{}", ir);
+    }
+
+    #[test]
+    fn building_unsupported_flags_fails() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.i32_type(), &[context.i32_type()], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+        let x = func.get_param(0).unwrap();
+        let flags = [BinaryOpFlag::NoSignedWrap, BinaryOpFlag::Exact];
+        match *builder.build_binop(BinaryOp::Add, x, x, &flags, "").unwrap_err().kind() {
+            enums::ErrorKind::UnsupportedFlag(BinaryOp::Add, BinaryOpFlag::Exact) => {}
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(entry.instructions().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "`or` can't have the nuw flag")]
+    fn setting_unsupported_flags_panics() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i32 @f(i32 %x) {
+              %y = or i32 %x, 1
+              ret i32 %y
+            }
+        ").unwrap();
+        let function = module.get_named_function("f").unwrap();
+        let or = function.entry_basic_block().unwrap().instructions().next().unwrap();
+        assert!(!or.try_as_binary_operator().unwrap().has_no_unsigned_wrap());
+        or.try_as_binary_operator().unwrap().set_no_unsigned_wrap(true);
+    }
+
+    #[test]
+    fn can_set_fast_math_flags() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let f64_type = <f64 as ContextType>::get_type_in_context(&context);
+        let func_ty = ::types::Function::new(f64_type, &[f64_type, f64_type], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+        let (x, y) = (func.get_param(0).unwrap(), func.get_param(1).unwrap());
+
+        builder.set_fast_math_flags(FastMathFlags::NO_NANS | FastMathFlags::NO_INFS);
+        let product = builder.build_fmul(x, y, "product").try_as_instruction().unwrap();
+        assert_eq!(product.fast_math_flags(), builder.fast_math_flags());
+        product.set_fast_math_flags(FastMathFlags::FAST);
+        let cmp = builder.build_fcmp(RealPredicate::OrderedLessThan, product, x, "cmp");
+        let select = builder.build_select(cmp, product, y, "select");
+        builder.set_fast_math_flags(FastMathFlags::empty());
+        let sum = builder.build_fadd(select, x, "sum").try_as_instruction().unwrap();
+        assert!(sum.fast_math_flags().is_empty());
+        sum.set_fast_math_flags(FastMathFlags::ALLOW_CONTRACT);
+        builder.build_ret(sum);
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("%product = fmul fast double %0, %1"), "{}", ir);
+        assert!(ir.contains("%cmp = fcmp nnan ninf olt double %product, %0"), "{}", ir);
+        assert!(ir.contains("%select = select nnan ninf i1 %cmp, double %product, double %1"), "{}", ir);
+        assert!(ir.contains("%sum = fadd contract double %select, %0"), "{}", ir);
+    }
+
+    #[test]
+    #[should_panic(expected = "`alloca` can't have fast math flags")]
+    fn fast_math_flags_need_floating_point_instructions() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+        builder.set_fast_math_flags(FastMathFlags::FAST);
+        let alloca = builder.build_alloca(context.i32_type(), "");
+        assert!(alloca.fast_math_flags().is_empty());
+        alloca.set_fast_math_flags(FastMathFlags::FAST);
+    }
+
+    #[test]
+    fn can_configure_call_sites() {
+        use value::{AttributeIndex, TailCallKind};
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            declare fastcc i32 @callee(i32)
+            declare i32 @variadic(i32, ...)
+            declare i32 @test(i32, i32 (i32)*)
+        ").unwrap();
+        let mut builder = context.create_builder();
+        let callee = module.get_named_function("callee").unwrap();
+        let variadic = module.get_named_function("variadic").unwrap();
+
+        let mut func = module.get_named_function("test").unwrap();
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+        let (x, fp) = (func.get_param(0).unwrap(), func.get_param(1).unwrap());
+
+        let direct = builder.build_call(callee, &[x], "direct");
+        assert_eq!(direct.called_value(), callee.as_value());
+        assert_eq!(direct.calling_convention(), Some(CallConv::C));
+        direct.set_calling_convention(CallConv::Fast);
+        direct.set_tail_call(true);
+        assert_eq!(direct.tail_call_kind(), TailCallKind::Tail);
+        direct.add_enum_attribute(AttributeIndex::Function, "nounwind", 0);
+        direct.add_enum_attribute(AttributeIndex::Param(0), "noundef", 0);
+        direct.add_string_attribute(AttributeIndex::Function, "kind", "direct");
+        assert_eq!(direct.enum_attribute(AttributeIndex::Function, "nounwind"), Some(0));
+        assert_eq!(direct.string_attribute(AttributeIndex::Function, "kind"), Some("direct".into()));
+        direct.remove_enum_attribute(AttributeIndex::Param(0), "noundef");
+        assert_eq!(direct.enum_attribute(AttributeIndex::Param(0), "noundef"), None);
+
+        let indirect = builder.build_indirect_call(direct.function_type(), fp, &[direct], "indirect");
+        indirect.set_calling_convention(CallConv::Fast);
+        let varargs = builder.build_call(variadic, &[indirect, x, indirect], "varargs");
+        varargs.set_tail_call_kind(TailCallKind::NoTail);
+        assert!(!varargs.is_tail_call());
+        let recursive = builder.build_call(func, &[varargs, fp], "recursive");
+        recursive.set_tail_call_kind(TailCallKind::MustTail);
+        assert_eq!(recursive.tail_call_kind(), TailCallKind::MustTail);
+        assert!(recursive.is_tail_call());
+        builder.build_ret(recursive);
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("%direct = tail call fastcc i32 @callee(i32 %0) #0"), "{}", ir);
+        assert!(ir.contains("%indirect = call fastcc i32 %1(i32 %direct)"), "{}", ir);
+        assert!(ir.contains("%varargs = notail call i32 (i32, ...) @variadic(i32 %indirect, i32 %0, i32 %indirect)"),
+                "{}", ir);
+        assert!(ir.contains("%recursive = musttail call i32 @test(i32 %varargs, i32 (i32)* %1)"), "{}", ir);
+        assert!(ir.contains("attributes #0 = { nounwind \"kind\"=\"direct\" }"), "{}", ir);
+
+        // Tail call kinds read back from parsed IR
+        let parsed = context.parse_ir(&ir).unwrap();
+        let kinds: Vec<_> = parsed.get_named_function("test").unwrap()
+            .entry_basic_block().unwrap()
+            .instructions()
+            .filter_map(|i| i.try_as_call_inst())
+            .map(|call| call.tail_call_kind())
+            .collect();
+        assert_eq!(kinds, [TailCallKind::Tail, TailCallKind::None, TailCallKind::NoTail,
+                           TailCallKind::MustTail]);
+    }
+}
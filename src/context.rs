@@ -1,12 +1,71 @@
+use std::fmt;
+use std::ops::Deref;
+
 use llvm_sys::prelude::*;
 use llvm_sys::core as llvm;
+use llvm_sys::LLVMContext;
 
 use super::*;
 
+use module::Result;
 use value::IntoConstValue;
 
 // LLVM Wrappers
 
+/// An LLVM context, which owns all the types, values and modules created in
+/// it.
+///
+/// # Lifetimes
+///
+/// Everything created from a `Context` borrows it for a `'ctx` lifetime:
+/// `Module<'ctx>`, `Builder<'ctx>`, constant `&'ctx Value`s and `&'ctx
+/// Type`s. Functions, basic blocks and instructions borrow the module they
+/// are in, and with it the context. The borrow checker then makes sure that
+/// none of them is used after the context is dropped:
+///
+/// ```rust,compile_fail,E0597
+/// let module = {
+///     let context = llvm::Context::new();
+///     context.module_create_with_name("module")
+/// }; // error: `context` does not live long enough
+/// ```
+///
+/// ```rust,compile_fail,E0505
+/// let context = llvm::Context::new();
+/// let mut builder = context.create_builder();
+/// drop(context); // error: cannot move out of `context` because it is borrowed
+/// builder.build_ret_void();
+/// ```
+///
+/// ```rust,compile_fail,E0597
+/// let value = {
+///     let context = llvm::Context::new();
+///     context.cons(1u32)
+/// }; // error: `context` does not live long enough
+/// ```
+///
+/// Objects of another context that are used with a module or builder have to
+/// outlive it as well:
+///
+/// ```rust,compile_fail,E0597
+/// let context = llvm::Context::new();
+/// let module = context.module_create_with_name("module");
+/// let mut builder = context.create_builder();
+/// let ty = llvm::types::Function::new(context.i64_type(), &[], false);
+/// let mut func = module.add_function(ty, "func");
+/// let bb = context.append_basic_block(&mut func, "entry");
+/// builder.position_at_end(bb);
+/// {
+///     let other = llvm::Context::new();
+///     builder.build_ret(other.cons(1i64));
+/// } // error: `other` does not live long enough
+/// builder.build_ret_void();
+/// ```
+///
+/// Lifetimes only make sure that objects don't outlive their context though,
+/// they don't tell contexts apart. Using a type or value of one context with
+/// a module or builder of another one compiles if both contexts live long
+/// enough, and must be avoided.
 #[derive(Debug)]
 pub struct Context {
     pub ptr: LLVMContextRef
@@ -20,86 +79,139 @@ impl Context {
         };
         Context { ptr: context }
     }
-    pub fn global() -> Self {
+
+    /// Returns LLVM's global context.
+    ///
+    /// The global context is never disposed of, so this returns a
+    /// `ContextRef` that lives for the rest of the program instead of an
+    /// owned `Context`.
+    ///
+    /// ```rust
+    /// use llvm::Context;
+    ///
+    /// {
+    ///     let context = Context::global();
+    ///     let module = context.module_create_with_name("first");
+    /// }
+    /// // The global context is still usable here
+    /// let module = Context::global().module_create_with_name("second");
+    /// ```
+    pub fn global() -> &'static ContextRef {
+        unsafe { llvm::LLVMGetGlobalContext().into() }
+    }
+}
+
+impl Deref for Context {
+    type Target = ContextRef;
+
+    fn deref(&self) -> &ContextRef {
+        self.ptr.into()
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
         unsafe {
-            Context {
-                ptr: llvm::LLVMGetGlobalContext()
-            }
+            llvm::LLVMContextDispose(self.ptr);
         }
     }
+}
 
-    pub fn create_builder(&self) -> Builder {
-        let builder = unsafe {
-            llvm::LLVMCreateBuilderInContext(self.ptr)
-        };
-        Builder { ptr: builder }
+impl Default for Context {
+    /// Creates a new context
+    fn default() -> Self {
+        Context::new()
     }
+}
+
+/// A borrowed LLVM context, which is never disposed of through this handle.
+///
+/// Owned `Context`s deref to `ContextRef`, and the global context is only
+/// ever available as a `&'static ContextRef`, so every API taking a
+/// `&ContextRef` accepts either kind of context.
+pub struct ContextRef(LLVMContext);
+impl_llvm_type_wrapper!(LLVMContextRef, ContextRef);
+impl_llvm_type_eq!(LLVMContextRef, ContextRef);
 
-    pub fn module_create_with_name(&self, name: &str) -> Module {
+impl fmt::Debug for ContextRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::ContextRef({:?})", LLVMContextRef::from(self))
+    }
+}
+
+impl ContextRef {
+    pub fn create_builder(&self) -> Builder<'_> {
+        unsafe {
+            llvm::LLVMCreateBuilderInContext(self.into()).into()
+        }
+    }
+
+    pub fn module_create_with_name(&self, name: &str) -> Module<'_> {
         let c_name = CString::new(name).unwrap();
-        let module = unsafe {
-            llvm::LLVMModuleCreateWithNameInContext(c_name.as_ptr(), self.ptr)
-        };
-        Module { ptr: module }
+        unsafe {
+            llvm::LLVMModuleCreateWithNameInContext(c_name.as_ptr(), self.into()).into()
+        }
+    }
+
+    /// Parses textual IR into a new module in this context.
+    ///
+    /// ```rust
+    /// let context = llvm::Context::new();
+    /// let module = context.parse_ir("@answer = global i32 42").unwrap();
+    /// assert!(module.to_string().contains("@answer"));
+    /// ```
+    pub fn parse_ir(&self, ir: &str) -> Result<Module<'_>> {
+        Module::parse_ir(self, MemoryBuffer::from_slice(ir.as_bytes(), "<string>"))
+    }
+
+    /// Parses bitcode into a new module in this context.
+    pub fn parse_bitcode(&self, bitcode: &[u8]) -> Result<Module<'_>> {
+        Module::parse_bitcode(self, &MemoryBuffer::from_slice(bitcode, "<bitcode>"))
     }
 
     pub fn void_type<'a>(&'a self) -> &'a types::Void {
-        unsafe { llvm::LLVMVoidTypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMVoidTypeInContext(self.into()).into() }
     }
 
     pub fn i1_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt1TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt1TypeInContext(self.into()).into() }
     }
 
     pub fn i8_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt8TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt8TypeInContext(self.into()).into() }
     }
 
     pub fn i16_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt16TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt16TypeInContext(self.into()).into() }
     }
 
     pub fn i32_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt32TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt32TypeInContext(self.into()).into() }
     }
 
     pub fn i64_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt64TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt64TypeInContext(self.into()).into() }
     }
 
     pub fn i128_type(&self) -> &types::Integer {
-        unsafe { llvm::LLVMInt128TypeInContext(self.ptr).into() }
+        unsafe { llvm::LLVMInt128TypeInContext(self.into()).into() }
     }
 
     pub fn integer_type(&self, num_bits: u32) -> &types::Integer {
-        unsafe { llvm::LLVMIntTypeInContext(self.ptr, num_bits).into() }
+        unsafe { llvm::LLVMIntTypeInContext(self.into(), num_bits).into() }
     }
 
-    pub fn append_basic_block(&self, func: &mut Function, name: &str) -> LLVMBasicBlockRef {
+    pub fn append_basic_block<'m>(&self, func: &mut Function<'m>,
+                                  name: &str) -> &'m value::BasicBlock {
         let c_name = CString::new(name).unwrap();
         unsafe {
-            llvm::LLVMAppendBasicBlockInContext(self.ptr, func.ptr, c_name.as_ptr())
+            llvm::LLVMAppendBasicBlockInContext(self.into(), func.ptr, c_name.as_ptr()).into()
         }
     }
 
     /// Creates a constant in this context
     /// The value must implement the trait `IntoValue`
-    pub fn cons<T: IntoConstValue>(&self, val: T) -> LLVMValueRef {
+    pub fn cons<T: IntoConstValue>(&self, val: T) -> &T::ConstType {
         val.gen_const(self)
     }
 }
-
-impl Drop for Context {
-    fn drop(&mut self) {
-        unsafe {
-            llvm::LLVMContextDispose(self.ptr);
-        }
-    }
-}
-
-impl Default for Context {
-    /// Returns the global context
-    fn default() -> Self {
-        Context::global()
-    }
-}
@@ -0,0 +1,749 @@
+//! Rust versions of the enums of the LLVM C API.
+//!
+//! Each enum converts from and into its `llvm_sys` counterpart, and is
+//! displayed and parsed with the spelling of LLVM's IR or command line tools:
+//!
+//! ```rust
+//! use llvm::{IntPredicate, OptLevel};
+//!
+//! assert_eq!(IntPredicate::SignedLessThan.to_string(), "slt");
+//! assert_eq!("3".parse::<OptLevel>().unwrap(), OptLevel::Aggressive);
+//! assert!("fast".parse::<OptLevel>().is_err());
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use llvm_sys::*;
+use llvm_sys::target_machine::*;
+
+error_chain! {
+    errors {
+        UnknownVariant(ty: &'static str, value: String) {
+            description("Unknown enum variant")
+            display("'{}' is not a valid {}", value, ty)
+        }
+        UnsupportedFlag(op: BinaryOp, flag: BinaryOpFlag) {
+            description("Binary operator can't have the flag")
+            display("'{}' can't have the {} flag", op, flag)
+        }
+    }
+}
+
+// An enum that is displayed and parsed with the given spellings
+macro_rules! spelled_enum {
+    ($(#[$attr: meta])*
+     pub enum $name: ident {
+         $($(#[$variant_attr: meta])* $variant: ident => $s: expr),* $(,)*
+     }) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_attr])* $variant),*
+        }
+
+        impl $name {
+            #[cfg(test)]
+            const ALL: &'static [$name] = &[$($name::$variant),*];
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let s = match *self {
+                    $($name::$variant => $s),*
+                };
+                write!(f, "{}", s)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                match s {
+                    $($s => Ok($name::$variant),)*
+                    _ => Err(ErrorKind::UnknownVariant(stringify!($name), s.into()).into()),
+                }
+            }
+        }
+    }
+}
+
+macro_rules! llvm_enum {
+    ($(#[$attr: meta])*
+     pub enum $name: ident: $llvm: ident {
+         $($(#[$variant_attr: meta])* $variant: ident = $llvm_variant: ident => $s: expr),* $(,)*
+     }) => {
+        spelled_enum! {
+            $(#[$attr])*
+            pub enum $name {
+                $($(#[$variant_attr])* $variant => $s),*
+            }
+        }
+
+        impl From<$llvm> for $name {
+            fn from(value: $llvm) -> Self {
+                match value {
+                    $($llvm::$llvm_variant => $name::$variant),*
+                }
+            }
+        }
+
+        impl From<$name> for $llvm {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $llvm::$llvm_variant),*
+                }
+            }
+        }
+    }
+}
+
+// An enum of some of the variants of `Opcode`, which converts into `Opcode`
+// and `LLVMOpcode`, and is displayed and parsed like them
+macro_rules! opcode_subset {
+    ($(#[$attr: meta])*
+     pub enum $name: ident {
+         $($(#[$variant_attr: meta])* $variant: ident),* $(,)*
+     }) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_attr])* $variant),*
+        }
+
+        impl $name {
+            #[cfg(test)]
+            const ALL: &'static [$name] = &[$($name::$variant),*];
+
+            /// Returns the variant with `opcode`, if there is one.
+            pub fn from_opcode(opcode: Opcode) -> Option<Self> {
+                match opcode {
+                    $(Opcode::$variant => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl From<$name> for Opcode {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => Opcode::$variant),*
+                }
+            }
+        }
+
+        impl From<$name> for LLVMOpcode {
+            fn from(value: $name) -> Self {
+                Opcode::from(value).into()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                Opcode::from(*self).fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                s.parse().ok().and_then($name::from_opcode)
+                    .ok_or_else(|| ErrorKind::UnknownVariant(stringify!($name), s.into()).into())
+            }
+        }
+    }
+}
+
+llvm_enum! {
+    /// The predicate of an integer comparison.
+    pub enum IntPredicate: LLVMIntPredicate {
+        Equal = LLVMIntEQ => "eq",
+        NotEqual = LLVMIntNE => "ne",
+        UnsignedGreaterThan = LLVMIntUGT => "ugt",
+        UnsignedGreaterOrEqual = LLVMIntUGE => "uge",
+        UnsignedLessThan = LLVMIntULT => "ult",
+        UnsignedLessOrEqual = LLVMIntULE => "ule",
+        SignedGreaterThan = LLVMIntSGT => "sgt",
+        SignedGreaterOrEqual = LLVMIntSGE => "sge",
+        SignedLessThan = LLVMIntSLT => "slt",
+        SignedLessOrEqual = LLVMIntSLE => "sle",
+    }
+}
+
+llvm_enum! {
+    /// The predicate of a floating point comparison. Ordered predicates are
+    /// false if an operand is NaN, unordered ones are true.
+    pub enum RealPredicate: LLVMRealPredicate {
+        False = LLVMRealPredicateFalse => "false",
+        OrderedEqual = LLVMRealOEQ => "oeq",
+        OrderedGreaterThan = LLVMRealOGT => "ogt",
+        OrderedGreaterOrEqual = LLVMRealOGE => "oge",
+        OrderedLessThan = LLVMRealOLT => "olt",
+        OrderedLessOrEqual = LLVMRealOLE => "ole",
+        OrderedNotEqual = LLVMRealONE => "one",
+        /// True if no operand is NaN
+        Ordered = LLVMRealORD => "ord",
+        /// True if an operand is NaN
+        Unordered = LLVMRealUNO => "uno",
+        UnorderedEqual = LLVMRealUEQ => "ueq",
+        UnorderedGreaterThan = LLVMRealUGT => "ugt",
+        UnorderedGreaterOrEqual = LLVMRealUGE => "uge",
+        UnorderedLessThan = LLVMRealULT => "ult",
+        UnorderedLessOrEqual = LLVMRealULE => "ule",
+        UnorderedNotEqual = LLVMRealUNE => "une",
+        True = LLVMRealPredicateTrue => "true",
+    }
+}
+
+llvm_enum! {
+    /// The opcode of an instruction.
+    pub enum Opcode: LLVMOpcode {
+        Ret = LLVMRet => "ret",
+        Br = LLVMBr => "br",
+        Switch = LLVMSwitch => "switch",
+        IndirectBr = LLVMIndirectBr => "indirectbr",
+        Invoke = LLVMInvoke => "invoke",
+        Unreachable = LLVMUnreachable => "unreachable",
+        CallBr = LLVMCallBr => "callbr",
+        FNeg = LLVMFNeg => "fneg",
+        Add = LLVMAdd => "add",
+        FAdd = LLVMFAdd => "fadd",
+        Sub = LLVMSub => "sub",
+        FSub = LLVMFSub => "fsub",
+        Mul = LLVMMul => "mul",
+        FMul = LLVMFMul => "fmul",
+        UDiv = LLVMUDiv => "udiv",
+        SDiv = LLVMSDiv => "sdiv",
+        FDiv = LLVMFDiv => "fdiv",
+        URem = LLVMURem => "urem",
+        SRem = LLVMSRem => "srem",
+        FRem = LLVMFRem => "frem",
+        Shl = LLVMShl => "shl",
+        LShr = LLVMLShr => "lshr",
+        AShr = LLVMAShr => "ashr",
+        And = LLVMAnd => "and",
+        Or = LLVMOr => "or",
+        Xor = LLVMXor => "xor",
+        Alloca = LLVMAlloca => "alloca",
+        Load = LLVMLoad => "load",
+        Store = LLVMStore => "store",
+        GetElementPtr = LLVMGetElementPtr => "getelementptr",
+        Trunc = LLVMTrunc => "trunc",
+        ZExt = LLVMZExt => "zext",
+        SExt = LLVMSExt => "sext",
+        FPToUI = LLVMFPToUI => "fptoui",
+        FPToSI = LLVMFPToSI => "fptosi",
+        UIToFP = LLVMUIToFP => "uitofp",
+        SIToFP = LLVMSIToFP => "sitofp",
+        FPTrunc = LLVMFPTrunc => "fptrunc",
+        FPExt = LLVMFPExt => "fpext",
+        PtrToInt = LLVMPtrToInt => "ptrtoint",
+        IntToPtr = LLVMIntToPtr => "inttoptr",
+        BitCast = LLVMBitCast => "bitcast",
+        AddrSpaceCast = LLVMAddrSpaceCast => "addrspacecast",
+        ICmp = LLVMICmp => "icmp",
+        FCmp = LLVMFCmp => "fcmp",
+        Phi = LLVMPHI => "phi",
+        Call = LLVMCall => "call",
+        Select = LLVMSelect => "select",
+        UserOp1 = LLVMUserOp1 => "userop1",
+        UserOp2 = LLVMUserOp2 => "userop2",
+        VAArg = LLVMVAArg => "va_arg",
+        ExtractElement = LLVMExtractElement => "extractelement",
+        InsertElement = LLVMInsertElement => "insertelement",
+        ShuffleVector = LLVMShuffleVector => "shufflevector",
+        ExtractValue = LLVMExtractValue => "extractvalue",
+        InsertValue = LLVMInsertValue => "insertvalue",
+        Freeze = LLVMFreeze => "freeze",
+        Fence = LLVMFence => "fence",
+        AtomicCmpXchg = LLVMAtomicCmpXchg => "cmpxchg",
+        AtomicRMW = LLVMAtomicRMW => "atomicrmw",
+        Resume = LLVMResume => "resume",
+        LandingPad = LLVMLandingPad => "landingpad",
+        CleanupRet = LLVMCleanupRet => "cleanupret",
+        CatchRet = LLVMCatchRet => "catchret",
+        CatchPad = LLVMCatchPad => "catchpad",
+        CleanupPad = LLVMCleanupPad => "cleanuppad",
+        CatchSwitch = LLVMCatchSwitch => "catchswitch",
+    }
+}
+
+opcode_subset! {
+    /// The opcode of a binary operator, see `Builder::build_binop`.
+    pub enum BinaryOp {
+        Add,
+        FAdd,
+        Sub,
+        FSub,
+        Mul,
+        FMul,
+        UDiv,
+        SDiv,
+        FDiv,
+        URem,
+        SRem,
+        FRem,
+        Shl,
+        LShr,
+        AShr,
+        And,
+        Or,
+        Xor,
+    }
+}
+
+spelled_enum! {
+    /// A unary operation, see `Builder::build_unop`. Only `fneg` is an
+    /// instruction of its own, the others are spelled like the `IRBuilder`
+    /// methods building them.
+    pub enum UnaryOp {
+        /// Integer negation, a `sub` from 0
+        Neg => "neg",
+        /// Floating point negation
+        FNeg => "fneg",
+        /// Bitwise negation, an `xor` with -1
+        Not => "not",
+    }
+}
+
+impl BinaryOp {
+    /// Returns whether this operator can have `flag`: `add`, `sub`, `mul`
+    /// and `shl` can have `nsw` and `nuw`, and `udiv`, `sdiv`, `lshr` and
+    /// `ashr` can be `exact`.
+    pub fn supports_flag(&self, flag: BinaryOpFlag) -> bool {
+        match flag {
+            BinaryOpFlag::NoSignedWrap | BinaryOpFlag::NoUnsignedWrap => {
+                matches!(*self, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Shl)
+            }
+            BinaryOpFlag::Exact => {
+                matches!(*self, BinaryOp::UDiv | BinaryOp::SDiv | BinaryOp::LShr | BinaryOp::AShr)
+            }
+        }
+    }
+}
+
+spelled_enum! {
+    /// A flag of a binary operator, see `Builder::build_binop`.
+    pub enum BinaryOpFlag {
+        /// Signed overflow is a poison value
+        NoSignedWrap => "nsw",
+        /// Unsigned overflow is a poison value
+        NoUnsignedWrap => "nuw",
+        /// A division with a remainder, or a shift shifting out non-zero
+        /// bits, is a poison value
+        Exact => "exact",
+    }
+}
+
+/// The fast math flags of a floating point instruction, which allow
+/// optimizations that don't preserve IEEE semantics. Flags are combined with
+/// `|` and displayed and parsed as they are written in IR:
+///
+/// ```rust
+/// use llvm::FastMathFlags;
+///
+/// let flags = FastMathFlags::NO_NANS | FastMathFlags::NO_INFS;
+/// assert!(flags.contains(FastMathFlags::NO_NANS));
+/// assert!(!flags.contains(FastMathFlags::FAST));
+/// assert_eq!(flags.to_string(), "nnan ninf");
+/// assert_eq!("ninf nnan".parse::<FastMathFlags>().unwrap(), flags);
+/// assert_eq!(!flags - FastMathFlags::APPROX_FUNC, "reassoc nsz arcp contract".parse().unwrap());
+/// assert_eq!(FastMathFlags::all().to_string(), "fast");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FastMathFlags(u32);
+
+impl FastMathFlags {
+    /// `reassoc`: operands may be reassociated
+    pub const ALLOW_REASSOC: FastMathFlags = FastMathFlags(1 << 0);
+    /// `nnan`: operands and results are assumed not to be NaN
+    pub const NO_NANS: FastMathFlags = FastMathFlags(1 << 1);
+    /// `ninf`: operands and results are assumed not to be infinite
+    pub const NO_INFS: FastMathFlags = FastMathFlags(1 << 2);
+    /// `nsz`: the sign of a zero is insignificant
+    pub const NO_SIGNED_ZEROS: FastMathFlags = FastMathFlags(1 << 3);
+    /// `arcp`: divisions may use the reciprocal of the divisor
+    pub const ALLOW_RECIPROCAL: FastMathFlags = FastMathFlags(1 << 4);
+    /// `contract`: operations may be fused, e.g. into a fused multiply-add
+    pub const ALLOW_CONTRACT: FastMathFlags = FastMathFlags(1 << 5);
+    /// `afn`: functions may be approximated
+    pub const APPROX_FUNC: FastMathFlags = FastMathFlags(1 << 6);
+    /// `fast`: all of the above
+    pub const FAST: FastMathFlags = FastMathFlags((1 << 7) - 1);
+
+    const NAMES: &[(FastMathFlags, &str)] = &[
+        (FastMathFlags::ALLOW_REASSOC, "reassoc"),
+        (FastMathFlags::NO_NANS, "nnan"),
+        (FastMathFlags::NO_INFS, "ninf"),
+        (FastMathFlags::NO_SIGNED_ZEROS, "nsz"),
+        (FastMathFlags::ALLOW_RECIPROCAL, "arcp"),
+        (FastMathFlags::ALLOW_CONTRACT, "contract"),
+        (FastMathFlags::APPROX_FUNC, "afn"),
+    ];
+
+    pub fn empty() -> Self {
+        FastMathFlags(0)
+    }
+
+    pub fn all() -> Self {
+        FastMathFlags::FAST
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the flags with `bits`, or `None` if a bit isn't a flag.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        if bits & !FastMathFlags::FAST.0 == 0 {
+            Some(FastMathFlags(bits))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the flags with `bits`, ignoring bits that aren't flags.
+    pub fn from_bits_truncate(bits: u32) -> Self {
+        FastMathFlags(bits & FastMathFlags::FAST.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == FastMathFlags::all()
+    }
+
+    /// Returns whether all of `other`'s flags are set in `self`.
+    pub fn contains(&self, other: FastMathFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: FastMathFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: FastMathFlags) {
+        self.0 &= !other.0;
+    }
+
+    pub fn toggle(&mut self, other: FastMathFlags) {
+        self.0 ^= other.0;
+    }
+}
+
+// Implements a binary operator and its assigning version on `FastMathFlags`
+macro_rules! fast_math_flags_op {
+    ($($op: ident::$f: ident, $assign_op: ident::$assign_f: ident => |$a: ident, $b: ident| $e: expr;)*) => {
+        $(impl ::std::ops::$op for FastMathFlags {
+            type Output = FastMathFlags;
+
+            fn $f(self, other: FastMathFlags) -> FastMathFlags {
+                let ($a, $b) = (self.0, other.0);
+                FastMathFlags($e)
+            }
+        }
+
+        impl ::std::ops::$assign_op for FastMathFlags {
+            fn $assign_f(&mut self, other: FastMathFlags) {
+                *self = ::std::ops::$op::$f(*self, other);
+            }
+        })*
+    }
+}
+
+fast_math_flags_op! {
+    BitOr::bitor, BitOrAssign::bitor_assign => |a, b| a | b;
+    BitAnd::bitand, BitAndAssign::bitand_assign => |a, b| a & b;
+    BitXor::bitxor, BitXorAssign::bitxor_assign => |a, b| a ^ b;
+    Sub::sub, SubAssign::sub_assign => |a, b| a & !b;
+}
+
+/// Returns the flags that aren't set in `self`.
+impl ::std::ops::Not for FastMathFlags {
+    type Output = FastMathFlags;
+
+    fn not(self) -> FastMathFlags {
+        FastMathFlags::from_bits_truncate(!self.0)
+    }
+}
+
+/// Displays the flags as they are written in IR, separated by spaces.
+impl fmt::Display for FastMathFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_all() {
+            return write!(f, "fast");
+        }
+        let names: Vec<&str> = FastMathFlags::NAMES.iter()
+            .filter(|&&(flag, _)| self.contains(flag))
+            .map(|&(_, name)| name)
+            .collect();
+        write!(f, "{}", names.join(" "))
+    }
+}
+
+/// Parses flags separated by whitespace, in any order.
+impl FromStr for FastMathFlags {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut flags = FastMathFlags::empty();
+        for name in s.split_whitespace() {
+            flags |= match FastMathFlags::NAMES.iter().find(|&&(_, n)| n == name) {
+                Some(&(flag, _)) => flag,
+                None if name == "fast" => FastMathFlags::FAST,
+                None => return Err(ErrorKind::UnknownVariant("FastMathFlags", name.into()).into()),
+            };
+        }
+        Ok(flags)
+    }
+}
+
+llvm_enum! {
+    /// The ordering constraint of an atomic operation.
+    pub enum AtomicOrdering: LLVMAtomicOrdering {
+        NotAtomic = LLVMAtomicOrderingNotAtomic => "not_atomic",
+        Unordered = LLVMAtomicOrderingUnordered => "unordered",
+        Monotonic = LLVMAtomicOrderingMonotonic => "monotonic",
+        Acquire = LLVMAtomicOrderingAcquire => "acquire",
+        Release = LLVMAtomicOrderingRelease => "release",
+        AcquireRelease = LLVMAtomicOrderingAcquireRelease => "acq_rel",
+        SequentiallyConsistent = LLVMAtomicOrderingSequentiallyConsistent => "seq_cst",
+    }
+}
+
+llvm_enum! {
+    /// The operation of an `atomicrmw` instruction.
+    pub enum AtomicRMWBinOp: LLVMAtomicRMWBinOp {
+        Xchg = LLVMAtomicRMWBinOpXchg => "xchg",
+        Add = LLVMAtomicRMWBinOpAdd => "add",
+        Sub = LLVMAtomicRMWBinOpSub => "sub",
+        And = LLVMAtomicRMWBinOpAnd => "and",
+        Nand = LLVMAtomicRMWBinOpNand => "nand",
+        Or = LLVMAtomicRMWBinOpOr => "or",
+        Xor = LLVMAtomicRMWBinOpXor => "xor",
+        /// Signed maximum
+        Max = LLVMAtomicRMWBinOpMax => "max",
+        /// Signed minimum
+        Min = LLVMAtomicRMWBinOpMin => "min",
+        UMax = LLVMAtomicRMWBinOpUMax => "umax",
+        UMin = LLVMAtomicRMWBinOpUMin => "umin",
+        FAdd = LLVMAtomicRMWBinOpFAdd => "fadd",
+        FSub = LLVMAtomicRMWBinOpFSub => "fsub",
+    }
+}
+
+// `CallConv` also converts from the number of a calling convention, since
+// instructions and functions store them as numbers
+macro_rules! call_conv_enum {
+    ($($variant: ident = $llvm_variant: ident => $s: expr),* $(,)*) => {
+        llvm_enum! {
+            /// The calling convention of a function or call site, displayed
+            /// and parsed with its IR spelling, e.g. `fastcc`. Conventions
+            /// without a keyword are spelled `cc <number>`.
+            pub enum CallConv: LLVMCallConv {
+                $($variant = $llvm_variant => $s),*
+            }
+        }
+
+        impl CallConv {
+            /// Returns the calling convention with the number `id`, if it
+            /// has a variant.
+            pub fn from_id(id: u32) -> Option<Self> {
+                $(if id == LLVMCallConv::$llvm_variant as u32 {
+                    return Some(CallConv::$variant);
+                })*
+                None
+            }
+
+            pub fn id(self) -> u32 {
+                LLVMCallConv::from(self) as u32
+            }
+        }
+    }
+}
+
+call_conv_enum! {
+    C = LLVMCCallConv => "ccc",
+    Fast = LLVMFastCallConv => "fastcc",
+    Cold = LLVMColdCallConv => "coldcc",
+    Ghc = LLVMGHCCallConv => "ghccc",
+    Hipe = LLVMHiPECallConv => "cc 11",
+    WebKitJS = LLVMWebKitJSCallConv => "webkit_jscc",
+    AnyReg = LLVMAnyRegCallConv => "anyregcc",
+    PreserveMost = LLVMPreserveMostCallConv => "preserve_mostcc",
+    PreserveAll = LLVMPreserveAllCallConv => "preserve_allcc",
+    Swift = LLVMSwiftCallConv => "swiftcc",
+    CxxFastTls = LLVMCXXFASTTLSCallConv => "cxx_fast_tlscc",
+    X86Stdcall = LLVMX86StdcallCallConv => "x86_stdcallcc",
+    X86Fastcall = LLVMX86FastcallCallConv => "x86_fastcallcc",
+    ArmApcs = LLVMARMAPCSCallConv => "arm_apcscc",
+    ArmAapcs = LLVMARMAAPCSCallConv => "arm_aapcscc",
+    ArmAapcsVfp = LLVMARMAAPCSVFPCallConv => "arm_aapcs_vfpcc",
+    Msp430Intr = LLVMMSP430INTRCallConv => "msp430_intrcc",
+    X86ThisCall = LLVMX86ThisCallCallConv => "x86_thiscallcc",
+    PtxKernel = LLVMPTXKernelCallConv => "ptx_kernel",
+    PtxDevice = LLVMPTXDeviceCallConv => "ptx_device",
+    SpirFunc = LLVMSPIRFUNCCallConv => "spir_func",
+    SpirKernel = LLVMSPIRKERNELCallConv => "spir_kernel",
+    IntelOclBi = LLVMIntelOCLBICallConv => "intel_ocl_bicc",
+    X86_64SysV = LLVMX8664SysVCallConv => "x86_64_sysvcc",
+    Win64 = LLVMWin64CallConv => "win64cc",
+    X86VectorCall = LLVMX86VectorCallCallConv => "x86_vectorcallcc",
+    Hhvm = LLVMHHVMCallConv => "hhvmcc",
+    HhvmC = LLVMHHVMCCallConv => "hhvm_ccc",
+    X86Intr = LLVMX86INTRCallConv => "x86_intrcc",
+    AvrIntr = LLVMAVRINTRCallConv => "avr_intrcc",
+    AvrSignal = LLVMAVRSIGNALCallConv => "avr_signalcc",
+    AvrBuiltin = LLVMAVRBUILTINCallConv => "cc 86",
+    AmdGpuVs = LLVMAMDGPUVSCallConv => "amdgpu_vs",
+    AmdGpuGs = LLVMAMDGPUGSCallConv => "amdgpu_gs",
+    AmdGpuPs = LLVMAMDGPUPSCallConv => "amdgpu_ps",
+    AmdGpuCs = LLVMAMDGPUCSCallConv => "amdgpu_cs",
+    AmdGpuKernel = LLVMAMDGPUKERNELCallConv => "amdgpu_kernel",
+    X86RegCall = LLVMX86RegCallCallConv => "x86_regcallcc",
+    AmdGpuHs = LLVMAMDGPUHSCallConv => "amdgpu_hs",
+    Msp430Builtin = LLVMMSP430BUILTINCallConv => "cc 94",
+    AmdGpuLs = LLVMAMDGPULSCallConv => "amdgpu_ls",
+    AmdGpuEs = LLVMAMDGPUESCallConv => "amdgpu_es",
+}
+
+llvm_enum! {
+    /// The optimization level of code generation, parsed from and displayed
+    /// as the number of the corresponding `-O` flag.
+    pub enum OptLevel: LLVMCodeGenOptLevel {
+        None = LLVMCodeGenLevelNone => "0",
+        Less = LLVMCodeGenLevelLess => "1",
+        Default = LLVMCodeGenLevelDefault => "2",
+        Aggressive = LLVMCodeGenLevelAggressive => "3",
+    }
+}
+
+llvm_enum! {
+    /// The relocation model of generated code.
+    pub enum RelocMode: LLVMRelocMode {
+        Default = LLVMRelocDefault => "default",
+        Static = LLVMRelocStatic => "static",
+        Pic = LLVMRelocPIC => "pic",
+        DynamicNoPic = LLVMRelocDynamicNoPic => "dynamic-no-pic",
+        Ropi = LLVMRelocROPI => "ropi",
+        Rwpi = LLVMRelocRWPI => "rwpi",
+        RopiRwpi = LLVMRelocROPI_RWPI => "ropi-rwpi",
+    }
+}
+
+llvm_enum! {
+    /// The code model of generated code, which limits the size and address
+    /// of code and data.
+    pub enum CodeModel: LLVMCodeModel {
+        Default = LLVMCodeModelDefault => "default",
+        JitDefault = LLVMCodeModelJITDefault => "jit-default",
+        Tiny = LLVMCodeModelTiny => "tiny",
+        Small = LLVMCodeModelSmall => "small",
+        Kernel = LLVMCodeModelKernel => "kernel",
+        Medium = LLVMCodeModelMedium => "medium",
+        Large = LLVMCodeModelLarge => "large",
+    }
+}
+
+llvm_enum! {
+    /// The kind of file emitted by a `TargetMachine`.
+    pub enum FileType: LLVMCodeGenFileType {
+        Assembly = LLVMAssemblyFile => "asm",
+        Object = LLVMObjectFile => "obj",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! check_round_trips {
+        ($($name: ident: $llvm: ident),*) => {
+            $(for &variant in $name::ALL {
+                assert_eq!($name::from($llvm::from(variant)), variant);
+                assert_eq!(variant.to_string().parse::<$name>().unwrap(), variant);
+            })*
+        }
+    }
+
+    #[test]
+    fn variants_round_trip() {
+        check_round_trips!(IntPredicate: LLVMIntPredicate,
+                           RealPredicate: LLVMRealPredicate,
+                           Opcode: LLVMOpcode,
+                           AtomicOrdering: LLVMAtomicOrdering,
+                           AtomicRMWBinOp: LLVMAtomicRMWBinOp,
+                           CallConv: LLVMCallConv,
+                           OptLevel: LLVMCodeGenOptLevel,
+                           RelocMode: LLVMRelocMode,
+                           CodeModel: LLVMCodeModel,
+                           FileType: LLVMCodeGenFileType);
+    }
+
+    #[test]
+    fn call_convs_convert_from_ids() {
+        for &cc in CallConv::ALL {
+            assert_eq!(CallConv::from_id(cc.id()), Some(cc));
+        }
+        assert_eq!(CallConv::Fast.id(), 8);
+        assert_eq!(CallConv::from_id(1), None);
+    }
+
+    #[test]
+    fn binary_ops_are_opcodes() {
+        for &op in BinaryOp::ALL {
+            assert_eq!(BinaryOp::from_opcode(op.into()), Some(op));
+            assert_eq!(op.to_string().parse::<BinaryOp>().unwrap(), op);
+        }
+        assert_eq!(BinaryOp::from_opcode(Opcode::ICmp), None);
+        assert!("icmp".parse::<BinaryOp>().is_err());
+    }
+
+    #[test]
+    fn spelled_variants_round_trip() {
+        for &op in UnaryOp::ALL {
+            assert_eq!(op.to_string().parse::<UnaryOp>().unwrap(), op);
+        }
+        for &flag in BinaryOpFlag::ALL {
+            assert_eq!(flag.to_string().parse::<BinaryOpFlag>().unwrap(), flag);
+        }
+        assert_eq!(UnaryOp::FNeg.to_string(), "fneg");
+        assert!("neg nsw".parse::<UnaryOp>().is_err());
+    }
+
+    #[test]
+    fn fast_math_flags_round_trip() {
+        for bits in 0..FastMathFlags::FAST.bits() + 1 {
+            let flags = FastMathFlags::from_bits(bits).unwrap();
+            assert_eq!(flags.to_string().parse::<FastMathFlags>().unwrap(), flags);
+            assert_eq!(!!flags, flags);
+            assert_eq!(flags ^ FastMathFlags::all(), !flags);
+        }
+        assert_eq!(FastMathFlags::from_bits(1 << 7), None);
+        assert_eq!(FastMathFlags::from_bits_truncate(!0), FastMathFlags::all());
+        assert_eq!("".parse::<FastMathFlags>().unwrap(), FastMathFlags::empty());
+        assert!("nnan nsw".parse::<FastMathFlags>().is_err());
+
+        let mut flags = FastMathFlags::all();
+        flags -= FastMathFlags::NO_NANS;
+        flags &= FastMathFlags::NO_NANS | FastMathFlags::NO_INFS;
+        assert_eq!(flags, FastMathFlags::NO_INFS);
+        flags ^= FastMathFlags::NO_INFS | FastMathFlags::APPROX_FUNC;
+        assert_eq!(flags, FastMathFlags::APPROX_FUNC);
+    }
+
+    #[test]
+    fn unknown_variants_are_errors() {
+        match *"fast".parse::<CodeModel>().unwrap_err().kind() {
+            ErrorKind::UnknownVariant(ty, ref value) => {
+                assert_eq!(ty, "CodeModel");
+                assert_eq!(value, "fast");
+            }
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -1,33 +1,776 @@
+use llvm_sys::LLVMTypeKind;
+use llvm_sys::core::{LLVMCountParamTypes, LLVMGetGlobalParent, LLVMGetIntTypeWidth,
+                     LLVMGetModuleContext, LLVMGetParamTypes, LLVMGetReturnType,
+                     LLVMGetTypeContext, LLVMGetTypeKind, LLVMGlobalGetValueType,
+                     LLVMPointerType};
 use llvm_sys::execution_engine::*;
+use llvm_sys::prelude::*;
+use llvm_sys::target_machine::LLVMCodeGenOptLevel;
 use super::*;
-use std::mem;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::{self, size_of};
+use value::GlobalValue;
 
-error_chain! {}
+error_chain! {
+    links {
+        Module(module::Error, module::ErrorKind);
+    }
+
+    errors {
+        UnknownModule {
+            description("Module is not owned by this execution engine")
+            display("Module is not owned by this execution engine")
+        }
+        ArgumentCount(name: String, expected: usize, found: usize) {
+            description("Function was run with the wrong number of arguments")
+            display("Function '{}' takes {} arguments, but was run with {}", name, expected, found)
+        }
+        ArgumentType(name: String, index: usize, expected: String) {
+            description("Function was run with an argument of the wrong type")
+            display("Argument {} of function '{}' must be a '{}'", index, name, expected)
+        }
+        UnknownFunction(name: String) {
+            description("Function is not defined in the execution engine")
+            display("Function '{}' is not defined in the execution engine", name)
+        }
+        SignatureMismatch(name: String, expected: String, found: String) {
+            description("Function has a different signature than requested")
+            display("Function '{}' has type '{}', but '{}' was requested", name, found, expected)
+        }
+    }
+}
+
+/// Types that can be passed to and returned from JIT compiled functions:
+/// integers, floating point numbers and pointers to other `JitType`s.
+///
+/// # Safety
+///
+/// Every value of the LLVM type returned by `jit_type` must be a valid value
+/// of the implementing type, passed the same way in the C calling
+/// convention. This rules out e.g. `bool`, which LLVM passes as an `i1` with
+/// undefined upper bits, and `char`, which can't hold every `i32`:
+///
+/// ```rust,compile_fail,E0277
+/// # use llvm::*;
+/// # let context = Context::new();
+/// let module = context.parse_ir("define i1 @f() {\n  ret i1 true\n}").unwrap();
+/// let ee = ExecutionEngine::create_for_module(module).unwrap();
+/// ee.get_function::<extern "C" fn() -> bool>("f").unwrap();
+/// ```
+pub unsafe trait JitType: Copy {
+    /// Returns the LLVM type of this type.
+    fn jit_type(context: &ContextRef) -> &Type;
+}
+
+macro_rules! impl_jit_type {
+    ($($t: ty),*) => {
+        $(unsafe impl JitType for $t {
+            fn jit_type(context: &ContextRef) -> &Type {
+                <$t as ContextType>::get_type_in_context(context)
+            }
+        })*
+    }
+}
+
+impl_jit_type!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+unsafe impl<T: JitType> JitType for *const T {
+    fn jit_type(context: &ContextRef) -> &Type {
+        unsafe { LLVMPointerType(T::jit_type(context).into(), 0).into() }
+    }
+}
+
+unsafe impl<T: JitType> JitType for *mut T {
+    fn jit_type(context: &ContextRef) -> &Type {
+        unsafe { LLVMPointerType(T::jit_type(context).into(), 0).into() }
+    }
+}
+
+/// Types that JIT compiled functions can return, i.e. `JitType`s and `()`
+/// for `void` functions.
+///
+/// # Safety
+///
+/// The same as for `JitType`.
+pub unsafe trait JitReturnType {
+    /// Returns the LLVM type of this type.
+    fn jit_type(context: &ContextRef) -> &Type;
+}
+
+unsafe impl<T: JitType> JitReturnType for T {
+    fn jit_type(context: &ContextRef) -> &Type {
+        <T as JitType>::jit_type(context)
+    }
+}
+
+unsafe impl JitReturnType for () {
+    fn jit_type(context: &ContextRef) -> &Type {
+        context.void_type()
+    }
+}
+
+/// Rust function pointer types that JIT compiled functions can be called
+/// through, i.e. `extern "C" fn`s whose parameters are `JitType`s and whose
+/// return type is a `JitReturnType`.
+///
+/// # Safety
+///
+/// Implementors must be function pointer types whose parameters and return
+/// value are passed the way `get_type_in_context` describes them to LLVM,
+/// since signatures are checked against that type before functions are
+/// called through them.
+pub unsafe trait JitSignature: Copy {
+    /// Returns the LLVM type of functions with this signature.
+    fn get_type_in_context(context: &ContextRef) -> &types::Function;
+
+    /// Converts the address of a function into a function pointer.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the address of a function with this signature,
+    /// which must stay alive for as long as the returned pointer is used.
+    unsafe fn from_address(address: u64) -> Self;
+
+    /// Returns the address of the function.
+    fn as_ptr(self) -> *const ();
+}
+
+macro_rules! impl_jit_signature {
+    ($($arg: ident),*) => {
+        unsafe impl<R: JitReturnType, $($arg: JitType),*> JitSignature for extern "C" fn($($arg),*) -> R {
+            fn get_type_in_context(context: &ContextRef) -> &types::Function {
+                types::Function::new(<R as JitReturnType>::jit_type(context),
+                                     &[$(<$arg as JitType>::jit_type(context)),*],
+                                     false)
+            }
+
+            unsafe fn from_address(address: u64) -> Self {
+                mem::transmute(address as usize)
+            }
+
+            fn as_ptr(self) -> *const () {
+                self as *const ()
+            }
+        }
+
+        impl<'ee, R, $($arg),*> JitFunction<'ee, extern "C" fn($($arg),*) -> R> {
+            /// Calls the function.
+            ///
+            /// # Safety
+            ///
+            /// Only the signature of the function was checked, the compiled
+            /// code itself can do anything. The caller must make sure that
+            /// it doesn't e.g. read or write memory it doesn't own, unwind
+            /// or keep pointers to the arguments beyond what their types
+            /// allow.
+            #[allow(non_snake_case)]
+            pub unsafe fn call(&self, $($arg: $arg),*) -> R {
+                (self.func)($($arg),*)
+            }
+        }
+    }
+}
+
+/// A JIT compiled function, which can't outlive the engine it belongs to.
+///
+/// ```rust,compile_fail,E0597
+/// # use llvm::*;
+/// # let context = Context::new();
+/// let f = {
+///     let module = context.parse_ir("define void @f() {\n  ret void\n}").unwrap();
+///     let ee = ExecutionEngine::create_for_module(module).unwrap();
+///     ee.get_function::<extern "C" fn()>("f").unwrap()
+/// }; // error: `ee` does not live long enough
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct JitFunction<'ee, F> {
+    func: F,
+    _marker: PhantomData<&'ee ()>,
+}
+
+impl<'ee, F: JitSignature> JitFunction<'ee, F> {
+    // The caller must make sure that `address` is a function with signature
+    // `F`, which lives for `'ee`
+    pub(crate) unsafe fn from_address(address: u64) -> Self {
+        JitFunction {
+            func: F::from_address(address),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl_jit_signature!();
+impl_jit_signature!(A);
+impl_jit_signature!(A, B);
+impl_jit_signature!(A, B, C);
+impl_jit_signature!(A, B, C, D);
+impl_jit_signature!(A, B, C, D, E);
+impl_jit_signature!(A, B, C, D, E, F);
+
+/// Options for MCJIT compilers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MCJITCompilerOptions {
+    pub opt_level: OptLevel,
+    pub code_model: CodeModel,
+    /// Whether to keep frame pointers in the generated code
+    pub no_frame_pointer_elim: bool,
+    /// Whether to use the fast instruction selector
+    pub fast_isel: bool,
+}
+
+impl Default for MCJITCompilerOptions {
+    /// Returns LLVM's default options
+    fn default() -> Self {
+        MCJITCompilerOptions {
+            opt_level: OptLevel::None,
+            code_model: CodeModel::JitDefault,
+            no_frame_pointer_elim: false,
+            fast_isel: false,
+        }
+    }
+}
+
+/// A value passed to or returned from `ExecutionEngine::run_function`.
+///
+/// ```rust
+/// use llvm::{Context, GenericValue};
+///
+/// let context = Context::new();
+/// let value = GenericValue::from_int(context.i32_type(), -2i64 as u64, true);
+/// assert_eq!(value.int_width(), 32);
+/// assert_eq!(value.as_i64(), -2);
+/// ```
+#[derive(Debug)]
+pub struct GenericValue {
+    pub ptr: LLVMGenericValueRef,
+    kind: Option<GenericKind>,
+}
+
+// The kind of value a `GenericValue` holds, which LLVM doesn't keep track of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GenericKind {
+    Int(u32),
+    Float,
+    Double,
+    Pointer,
+}
+
+impl GenericKind {
+    // Returns the kind of the values of type `ty`, if it's one that
+    // `GenericValue`s can be created for
+    unsafe fn of_type(ty: LLVMTypeRef) -> Option<GenericKind> {
+        match LLVMGetTypeKind(ty) {
+            LLVMTypeKind::LLVMIntegerTypeKind => Some(GenericKind::Int(LLVMGetIntTypeWidth(ty))),
+            LLVMTypeKind::LLVMFloatTypeKind => Some(GenericKind::Float),
+            LLVMTypeKind::LLVMDoubleTypeKind => Some(GenericKind::Double),
+            LLVMTypeKind::LLVMPointerTypeKind => Some(GenericKind::Pointer),
+            _ => None,
+        }
+    }
+}
+
+// Values wrapped this way can't be passed to `run_function`, since their
+// kind is unknown
+impl From<LLVMGenericValueRef> for GenericValue {
+    fn from(ptr: LLVMGenericValueRef) -> Self {
+        GenericValue {
+            ptr: ptr,
+            kind: None,
+        }
+    }
+}
+
+impl From<GenericValue> for LLVMGenericValueRef {
+    fn from(value: GenericValue) -> Self {
+        // The caller takes over ownership of the pointer
+        let ptr = value.ptr;
+        mem::forget(value);
+        ptr
+    }
+}
+
+impl GenericValue {
+    fn new(ptr: LLVMGenericValueRef, kind: GenericKind) -> GenericValue {
+        GenericValue {
+            ptr: ptr,
+            kind: Some(kind),
+        }
+    }
+
+    /// Creates an integer of type `ty`, which is sign extended from its
+    /// width if `signed` is set.
+    pub fn from_int(ty: &types::Integer, value: u64, signed: bool) -> GenericValue {
+        unsafe {
+            GenericValue::new(LLVMCreateGenericValueOfInt(ty.into(), value, signed as LLVMBool),
+                              GenericKind::Int(ty.width()))
+        }
+    }
+
+    pub fn from_f32(value: f32) -> GenericValue {
+        unsafe {
+            GenericValue::new(
+                LLVMCreateGenericValueOfFloat(f32::get_type_in_context(Context::global()).into(),
+                                              value as f64),
+                GenericKind::Float)
+        }
+    }
 
+    pub fn from_f64(value: f64) -> GenericValue {
+        unsafe {
+            GenericValue::new(
+                LLVMCreateGenericValueOfFloat(f64::get_type_in_context(Context::global()).into(),
+                                              value),
+                GenericKind::Double)
+        }
+    }
+
+    pub fn from_pointer<T>(pointer: *mut T) -> GenericValue {
+        unsafe {
+            GenericValue::new(LLVMCreateGenericValueOfPointer(pointer as *mut _),
+                              GenericKind::Pointer)
+        }
+    }
+
+    /// Returns the width of an integer value in bits.
+    pub fn int_width(&self) -> u32 {
+        unsafe {
+            LLVMGenericValueIntWidth(self.ptr)
+        }
+    }
+
+    /// Returns an integer value, zero extended to 64 bits.
+    pub fn as_u64(&self) -> u64 {
+        unsafe {
+            LLVMGenericValueToInt(self.ptr, 0)
+        }
+    }
+
+    /// Returns an integer value, sign extended to 64 bits.
+    pub fn as_i64(&self) -> i64 {
+        unsafe {
+            LLVMGenericValueToInt(self.ptr, 1) as i64
+        }
+    }
+
+    pub fn as_f32(&self) -> f32 {
+        unsafe {
+            LLVMGenericValueToFloat(f32::get_type_in_context(Context::global()).into(),
+                                    self.ptr) as f32
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        unsafe {
+            LLVMGenericValueToFloat(f64::get_type_in_context(Context::global()).into(),
+                                    self.ptr)
+        }
+    }
+
+    pub fn as_pointer<T>(&self) -> *mut T {
+        unsafe {
+            LLVMGenericValueToPointer(self.ptr) as *mut T
+        }
+    }
+}
+
+impl Drop for GenericValue {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeGenericValue(self.ptr);
+        }
+    }
+}
+
+// Returns the parameter types of the function type `ty`
+unsafe fn param_types(ty: LLVMTypeRef) -> Vec<LLVMTypeRef> {
+    let mut params = vec![::std::ptr::null_mut(); LLVMCountParamTypes(ty) as usize];
+    LLVMGetParamTypes(ty, params.as_mut_ptr());
+    params
+}
+
+/// Identifies a module owned by an `ExecutionEngine`, so that it can be
+/// removed from the engine again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleKey(LLVMModuleRef);
+
+/// An execution engine, which owns the modules that are added to it.
+///
+/// Modules are moved into the engine, and can only be taken back with
+/// `remove_module`, so a module can't be changed or disposed of while the
+/// engine is using it.
+///
+/// Once `run_static_constructors` was called, the static destructors of the
+/// modules are run when the engine is dropped, unless
+/// `run_static_destructors` was called before.
 #[derive(Debug)]
-pub struct ExecutionEngine {
+pub struct ExecutionEngine<'ctx> {
     pub ptr: LLVMExecutionEngineRef,
+    modules: Vec<ModuleKey>,
+    // Whether static destructors are still to be run
+    pending_destructors: bool,
+    _marker: PhantomData<Cell<&'ctx ContextRef>>,
+}
+
+impl<'ctx> From<ExecutionEngine<'ctx>> for LLVMExecutionEngineRef {
+    fn from(ee: ExecutionEngine<'ctx>) -> Self {
+        // The caller takes over ownership of the engine and its modules
+        let ptr = ee.ptr;
+        mem::forget(ee);
+        ptr
+    }
 }
-impl_llvm_ref!(ExecutionEngine, LLVMExecutionEngineRef);
 
-impl ExecutionEngine {
-    pub fn create_for_module(module: &Module) -> Result<ExecutionEngine> {
+impl<'ctx> ExecutionEngine<'ctx> {
+    // Creates an execution engine for `module` with `create`, taking
+    // ownership of the module
+    fn create<F>(module: Module<'ctx>, create: F) -> Result<ExecutionEngine<'ctx>>
+        where F: FnOnce(&mut LLVMExecutionEngineRef, LLVMModuleRef, &mut *mut i8) -> LLVMBool
+    {
+        module.verify_before_codegen()?;
+
+        // LLVM takes ownership of the module, even if creating the engine
+        // fails
+        let module_ptr: LLVMModuleRef = module.into();
+        let mut ee = ::std::ptr::null_mut();
+        let mut out = ::std::ptr::null_mut();
+
+        if create(&mut ee, module_ptr, &mut out) == 0 { // no error message was set
+            Ok(ExecutionEngine {
+                ptr: ee,
+                modules: vec![ModuleKey(module_ptr)],
+                pending_destructors: false,
+                _marker: PhantomData,
+            })
+        } else {
+            Err(unsafe { take_message(out) }.into())
+        }
+    }
+
+    /// Creates an execution engine for `module`, taking ownership of it.
+    ///
+    /// This creates a JIT compiler if `link_in_mcjit` was called, and an
+    /// interpreter otherwise. The module is verified first if that was
+    /// enabled with `Module::set_verify_before_codegen`.
+    pub fn create_for_module(module: Module<'ctx>) -> Result<ExecutionEngine<'ctx>> {
+        ExecutionEngine::create(module, |ee, module, out| unsafe {
+            LLVMCreateExecutionEngineForModule(ee, module, out)
+        })
+    }
+
+    /// Creates an interpreter for `module`, taking ownership of it.
+    ///
+    /// `link_in_interpreter` must have been called before. The module is
+    /// verified first if that was enabled with
+    /// `Module::set_verify_before_codegen`.
+    pub fn create_interpreter_for_module(module: Module<'ctx>) -> Result<ExecutionEngine<'ctx>> {
+        ExecutionEngine::create(module, |ee, module, out| unsafe {
+            LLVMCreateInterpreterForModule(ee, module, out)
+        })
+    }
+
+    /// Creates an MCJIT compiler for `module` with the given options, taking
+    /// ownership of the module.
+    ///
+    /// `link_in_mcjit` must have been called before. The module is verified
+    /// first if that was enabled with `Module::set_verify_before_codegen`.
+    pub fn create_mcjit_compiler_for_module(module: Module<'ctx>,
+                                            options: &MCJITCompilerOptions)
+                                            -> Result<ExecutionEngine<'ctx>> {
+        ExecutionEngine::create(module, |ee, module, out| unsafe {
+            let mut llvm_options = mem::zeroed::<LLVMMCJITCompilerOptions>();
+            let size = size_of::<LLVMMCJITCompilerOptions>();
+            LLVMInitializeMCJITCompilerOptions(&mut llvm_options, size);
+            llvm_options.OptLevel = LLVMCodeGenOptLevel::from(options.opt_level) as u32;
+            llvm_options.CodeModel = options.code_model.into();
+            llvm_options.NoFramePointerElim = options.no_frame_pointer_elim as LLVMBool;
+            llvm_options.EnableFastISel = options.fast_isel as LLVMBool;
+            LLVMCreateMCJITCompilerForModule(ee, module, &mut llvm_options, size, out)
+        })
+    }
+
+    /// Adds `module` to this engine, which takes ownership of it. The
+    /// returned key can be used to remove the module again.
+    pub fn add_module(&mut self, module: Module<'ctx>) -> ModuleKey {
+        let key = ModuleKey(module.into());
         unsafe {
-            let mut ee = mem::uninitialized();
+            LLVMAddModule(self.ptr, key.0);
+        }
+        self.modules.push(key);
+        key
+    }
+
+    /// Removes a module from this engine, handing its ownership back to the
+    /// caller.
+    pub fn remove_module(&mut self, key: ModuleKey) -> Result<Module<'ctx>> {
+        let index = match self.modules.iter().position(|&k| k == key) {
+            Some(index) => index,
+            None => bail!(ErrorKind::UnknownModule),
+        };
+        unsafe {
+            let mut module = mem::zeroed();
             let mut out = mem::zeroed();
 
-            let res = LLVMCreateExecutionEngineForModule(&mut ee, module.ptr, &mut out);
+            if LLVMRemoveModule(self.ptr, key.0, &mut module, &mut out) == 0 {
+                self.modules.remove(index);
+                Ok(module.into())
+            } else {
+                Err(take_message(out).into())
+            }
+        }
+    }
+
+    /// Runs the static constructors of the modules of this engine, i.e. the
+    /// functions in their `llvm.global_ctors` arrays.
+    ///
+    /// This must be called before running code that relies on global
+    /// initializers, e.g. those added with `Module::add_global_ctor`.
+    ///
+    /// # Safety
+    ///
+    /// The constructors can do anything, the caller must make sure it is
+    /// sound to run them. Afterwards, the static destructors run when the
+    /// engine is dropped unless `run_static_destructors` was called, so the
+    /// caller must also make sure that this is sound wherever the engine may
+    /// be dropped, e.g. that the host data they use is still alive.
+    pub unsafe fn run_static_constructors(&mut self) {
+        LLVMRunStaticConstructors(self.ptr);
+        self.pending_destructors = true;
+    }
+
+    /// Runs the static destructors of the modules of this engine, i.e. the
+    /// functions in their `llvm.global_dtors` arrays.
+    ///
+    /// They're run when the engine is dropped otherwise, if static
+    /// constructors were run.
+    ///
+    /// # Safety
+    ///
+    /// The destructors can do anything, the caller must make sure it is
+    /// sound to run them.
+    pub unsafe fn run_static_destructors(&mut self) {
+        LLVMRunStaticDestructors(self.ptr);
+        self.pending_destructors = false;
+    }
+
+    /// Returns the keys of all the modules owned by this engine, starting
+    /// with the one it was created for.
+    pub fn modules(&self) -> &[ModuleKey] {
+        &self.modules
+    }
+
+    /// Makes compiled code use `address` for `global`, which must be
+    /// declared but not defined, e.g. to let it call a Rust function or
+    /// access Rust data.
+    ///
+    /// The mapping must be added before code using `global` is compiled,
+    /// i.e. before the first function of its module is looked up, and
+    /// `address` must stay valid for as long as that code is used.
+    pub fn add_global_mapping<G: AsRef<GlobalValue> + ?Sized>(&mut self,
+                                                              global: &G,
+                                                              address: *const ()) {
+        let global: &Value = global.as_ref();
+        unsafe {
+            LLVMAddGlobalMapping(self.ptr, global.into(), address as *mut _);
+        }
+    }
 
-            if res == 0 { // no error message was set
-                Ok(ExecutionEngine{
-                    ptr: ee
-                })
+    /// Declares the Rust function `function` in `module` as `name`, with the
+    /// LLVM type matching its signature, and maps the declaration to it.
+    ///
+    /// `module` can then be added to this engine, and calls to the
+    /// declaration will call the Rust function.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    ///
+    /// extern "C" fn square(x: i64) -> i64 {
+    ///     x * x
+    /// }
+    ///
+    /// link_in_mcjit();
+    /// initialize_native_target();
+    /// initialize_native_asm_printer();
+    ///
+    /// let context = Context::new();
+    /// let mut ee = ExecutionEngine::create_for_module(
+    ///     context.module_create_with_name("host")).unwrap();
+    ///
+    /// let module = context.module_create_with_name("module");
+    /// ee.add_extern_function(&module, "square", square as extern "C" fn(i64) -> i64);
+    /// // ... build code calling `square` in `module`
+    /// ee.add_module(module);
+    /// ```
+    pub fn add_extern_function<'m, F: JitSignature>(&mut self,
+                                                    module: &'m Module<'ctx>,
+                                                    name: &str,
+                                                    function: F) -> Function<'m> {
+        let context: &'ctx ContextRef = unsafe { LLVMGetModuleContext(module.ptr).into() };
+        let declaration = module.add_function(F::get_type_in_context(context), name);
+        self.add_global_mapping(&declaration, function.as_ptr());
+        declaration
+    }
+
+    /// Looks up the compiled function `name`, checking that its LLVM type
+    /// matches the signature `F`.
+    ///
+    /// Rust types are matched by their `JitType`, so e.g. `i64` and
+    /// `u64` parameters are interchangeable.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    ///
+    /// link_in_mcjit();
+    /// initialize_native_target();
+    /// initialize_native_asm_printer();
+    ///
+    /// let context = Context::new();
+    /// let module = context.parse_ir("
+    ///     define i64 @sub(i64 %x, i64 %y) {
+    ///       %z = sub i64 %x, %y
+    ///       ret i64 %z
+    ///     }
+    /// ").unwrap();
+    /// let ee = ExecutionEngine::create_for_module(module).unwrap();
+    ///
+    /// let sub = ee.get_function::<extern "C" fn(i64, i64) -> i64>("sub").unwrap();
+    /// assert_eq!(unsafe { sub.call(5, 3) }, 2);
+    ///
+    /// assert!(ee.get_function::<extern "C" fn(i32) -> i64>("sub").is_err());
+    /// ```
+    pub fn get_function<'ee, F: JitSignature>(&'ee self, name: &str) -> Result<JitFunction<'ee, F>> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let mut function = mem::zeroed();
+            if LLVMFindFunction(self.ptr, c_name.as_ptr(), &mut function) != 0 {
+                bail!(ErrorKind::UnknownFunction(name.into()));
+            }
+
+            let found: &Type = LLVMGlobalGetValueType(function).into();
+            let context: &ContextRef = LLVMGetTypeContext(found.into()).into();
+            let expected: &Type = F::get_type_in_context(context);
+            if found != expected {
+                bail!(ErrorKind::SignatureMismatch(name.into(),
+                                                   expected.to_string(),
+                                                   found.to_string()));
+            }
+
+            let address = LLVMGetFunctionAddress(self.ptr, c_name.as_ptr());
+            if address == 0 {
+                bail!(ErrorKind::UnknownFunction(name.into()));
+            }
+            Ok(JitFunction::from_address(address))
+        }
+    }
+
+    /// Returns the function `name` of one of the modules of this engine, e.g.
+    /// to run it with `run_function`.
+    pub fn find_function(&self, name: &str) -> Option<Function<'_>> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let mut function = mem::zeroed();
+            if LLVMFindFunction(self.ptr, c_name.as_ptr(), &mut function) == 0 {
+                Some(Function::from_value_ref(function))
             } else {
-                Err(c_str_to_str!(out as *const i8).into())
+                None
             }
         }
     }
 
+    /// Runs `function` with `args`, returning its result.
+    ///
+    /// Interpreters can run any function this way, but JIT compilers only
+    /// support functions with a signature like `main`, or without
+    /// arguments; use `get_function` to call other compiled functions.
+    ///
+    /// Returns an error if `function` isn't part of a module of this
+    /// engine, or if `args` don't match its parameters.
+    ///
+    /// # Safety
+    ///
+    /// The function can do anything, e.g. dereference pointers it's given.
+    /// The caller must make sure it is sound to run it with `args`, and that
+    /// this engine can run it; LLVM aborts if a JIT compiler is asked to
+    /// run a function with an unsupported signature.
+    pub unsafe fn run_function(&self,
+                               function: &Function,
+                               args: &[GenericValue]) -> Result<GenericValue> {
+        self.check_owns(function)?;
+        let ty = LLVMGlobalGetValueType(function.ptr);
+        let params = param_types(ty);
+        if args.len() != params.len() {
+            bail!(ErrorKind::ArgumentCount(function.name(), params.len(), args.len()));
+        }
+        for (index, (arg, &param)) in args.iter().zip(&params).enumerate() {
+            if arg.kind.is_none() || arg.kind != GenericKind::of_type(param) {
+                let expected = <&Type>::from(param).to_string();
+                bail!(ErrorKind::ArgumentType(function.name(), index, expected));
+            }
+        }
+
+        let mut args: Vec<LLVMGenericValueRef> = args.iter().map(|arg| arg.ptr).collect();
+        let result = LLVMRunFunction(self.ptr, function.ptr, args.len() as u32, args.as_mut_ptr());
+        Ok(GenericValue {
+            ptr: result,
+            kind: GenericKind::of_type(LLVMGetReturnType(ty)),
+        })
+    }
+
+    /// Runs `function` as a `main` function, with the program arguments
+    /// `args` and the environment `env`, whose entries look like
+    /// `"NAME=value"`. Returns the exit code of `function`.
+    ///
+    /// Returns an error if `function` isn't part of a module of this
+    /// engine, or if its type isn't `i32 ()`, `i32 (i32, i8**)` or
+    /// `i32 (i32, i8**, i8**)`.
+    ///
+    /// # Safety
+    ///
+    /// The function can do anything, the caller must make sure it is sound
+    /// to run it.
+    pub unsafe fn run_function_as_main(&self,
+                                       function: &Function,
+                                       args: &[&str],
+                                       env: &[&str]) -> Result<i32> {
+        self.check_owns(function)?;
+        let ty = LLVMGlobalGetValueType(function.ptr);
+        let params: Vec<_> = param_types(ty).into_iter().map(|p| GenericKind::of_type(p)).collect();
+        let int = Some(GenericKind::Int(32));
+        let ptr = Some(GenericKind::Pointer);
+        let valid = GenericKind::of_type(LLVMGetReturnType(ty)) == int && match params.len() {
+            0 => true,
+            2 => params[..] == [int, ptr],
+            3 => params[..] == [int, ptr, ptr],
+            _ => false,
+        };
+        if !valid {
+            bail!(ErrorKind::SignatureMismatch(function.name(),
+                                               "i32 (i32, i8**, i8**)".into(),
+                                               <&Type>::from(ty).to_string()));
+        }
+
+        let c_args: Vec<CString> = args.iter().map(|&a| CString::new(a).unwrap()).collect();
+        let c_env: Vec<CString> = env.iter().map(|&e| CString::new(e).unwrap()).collect();
+        let argv: Vec<*const i8> = c_args.iter().map(|a| a.as_ptr()).collect();
+        // The environment is terminated by a null pointer
+        let envp: Vec<*const i8> = c_env.iter()
+            .map(|e| e.as_ptr())
+            .chain(Some(::std::ptr::null()))
+            .collect();
+        Ok(LLVMRunFunctionAsMain(self.ptr, function.ptr, argv.len() as u32,
+                                 argv.as_ptr(), envp.as_ptr()))
+    }
+
+    // Makes sure that `function` is part of one of the modules of this
+    // engine
+    fn check_owns(&self, function: &Function) -> Result<()> {
+        let module = unsafe { LLVMGetGlobalParent(function.ptr) };
+        if !self.modules.contains(&ModuleKey(module)) {
+            bail!(ErrorKind::UnknownFunction(function.name()));
+        }
+        Ok(())
+    }
+
     pub fn get_function_address(&self, fname: &str) -> Option<extern "C" fn()> {
         let fname_s = CString::new(fname).unwrap();
         unsafe {
@@ -42,9 +785,12 @@ impl ExecutionEngine {
     }
 }
 
-impl Drop for ExecutionEngine {
+impl<'ctx> Drop for ExecutionEngine<'ctx> {
     fn drop(&mut self) {
         unsafe {
+            if self.pending_destructors {
+                LLVMRunStaticDestructors(self.ptr);
+            }
             LLVMDisposeExecutionEngine(self.ptr);
         }
     }
@@ -55,3 +801,320 @@ pub fn link_in_mcjit() {
         LLVMLinkInMCJIT();
     }
 }
+
+// The interpreter calls external functions through libffi, which LLVM's
+// static libraries don't link themselves
+#[link(name = "ffi")]
+extern "C" {}
+
+pub fn link_in_interpreter() {
+    unsafe {
+        LLVMLinkInInterpreter();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_add_and_remove_modules() {
+        link_in_mcjit();
+        initialize_native_target();
+
+        let context = Context::new();
+        let first = context.module_create_with_name("first");
+        let second = context.module_create_with_name("second");
+
+        let mut ee = ExecutionEngine::create_for_module(first).unwrap();
+        let key = ee.add_module(second);
+        assert_eq!(ee.modules().len(), 2);
+
+        let second = ee.remove_module(key).unwrap();
+        assert!(second.to_string().contains("second"));
+        assert_eq!(ee.modules().len(), 1);
+        assert!(ee.remove_module(key).is_err());
+    }
+
+    #[test]
+    fn get_function_checks_signatures() {
+        link_in_mcjit();
+        initialize_native_target();
+        initialize_native_asm_printer();
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            define double @half(double %x) {
+              %y = fmul double %x, 0.5
+              ret double %y
+            }
+
+            define void @nothing() {
+              ret void
+            }
+
+            define i64 @load(i64* %p) {
+              %x = load i64, i64* %p
+              ret i64 %x
+            }
+
+            declare i32 @external()
+        ").unwrap();
+        let ee = ExecutionEngine::create_for_module(module).unwrap();
+
+        let half = ee.get_function::<extern "C" fn(f64) -> f64>("half").unwrap();
+        assert_eq!(unsafe { half.call(3.0) }, 1.5);
+        let nothing = ee.get_function::<extern "C" fn()>("nothing").unwrap();
+        unsafe { nothing.call() };
+        let load = ee.get_function::<extern "C" fn(*const i64) -> i64>("load").unwrap();
+        assert_eq!(unsafe { load.call(&42) }, 42);
+        assert!(ee.get_function::<extern "C" fn(*const i32) -> i64>("load").is_err());
+
+        match *ee.get_function::<extern "C" fn(f32) -> f64>("half").unwrap_err().kind() {
+            ErrorKind::SignatureMismatch(ref name, ref expected, ref found) => {
+                assert_eq!(name, "half");
+                assert_eq!(expected, "double (float)");
+                assert_eq!(found, "double (double)");
+            }
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+        match *ee.get_function::<extern "C" fn()>("missing").unwrap_err().kind() {
+            ErrorKind::UnknownFunction(ref name) => assert_eq!(name, "missing"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    // Calls the closure `closure` points to
+    extern "C" fn call_closure(closure: u64, x: i64) -> i64 {
+        let closure = unsafe { &mut *(closure as *mut &mut dyn FnMut(i64) -> i64) };
+        closure(x)
+    }
+
+    #[test]
+    fn jit_code_can_call_rust_closures() {
+        link_in_mcjit();
+        initialize_native_target();
+        initialize_native_asm_printer();
+
+        let context = Context::new();
+        let mut ee = ExecutionEngine::create_for_module(
+            context.module_create_with_name("host")).unwrap();
+
+        let module = context.module_create_with_name("module");
+        let callback = ee.add_extern_function(&module,
+                                              "callback",
+                                              call_closure as extern "C" fn(u64, i64) -> i64);
+        let i64_type = context.i64_type();
+        let ty = types::Function::new(i64_type, &[i64_type, i64_type], false);
+        let mut apply = module.add_function(ty, "apply");
+        let bb = context.append_basic_block(&mut apply, "entry");
+        let mut builder = context.create_builder();
+        builder.position_at_end(bb);
+        let args = vec![apply.get_param(0).unwrap() as &Value, apply.get_param(1).unwrap()];
+        let first = builder.build_call(callback, &args, "first");
+        let second = builder.build_call(callback, &[args[0], first], "second");
+        builder.build_ret(second);
+        drop(builder);
+        ee.add_module(module);
+
+        let mut seen = Vec::new();
+        let result = {
+            let mut closure = |x: i64| {
+                seen.push(x);
+                x + 1
+            };
+            let mut closure: &mut dyn FnMut(i64) -> i64 = &mut closure;
+            let env = &mut closure as *mut &mut dyn FnMut(i64) -> i64 as u64;
+
+            let apply = ee.get_function::<extern "C" fn(u64, i64) -> i64>("apply").unwrap();
+            unsafe { apply.call(env, 10) }
+        };
+        assert_eq!(result, 12);
+        assert_eq!(seen, vec![10, 11]);
+    }
+
+    #[test]
+    fn jit_code_can_access_rust_data() {
+        link_in_mcjit();
+        initialize_native_target();
+        initialize_native_asm_printer();
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            @counter = external global i64
+
+            define i64 @increment() {
+              %old = load i64, i64* @counter
+              %new = add i64 %old, 1
+              store i64 %new, i64* @counter
+              ret i64 %new
+            }
+        ").unwrap();
+        let mut ee = ExecutionEngine::create_for_module(
+            context.module_create_with_name("host")).unwrap();
+
+        let mut counter = Box::new(41i64);
+        ee.add_global_mapping(module.get_named_global("counter").unwrap(),
+                              &mut *counter as *mut i64 as *const ());
+        ee.add_module(module);
+
+        let increment = ee.get_function::<extern "C" fn() -> i64>("increment").unwrap();
+        assert_eq!(unsafe { increment.call() }, 42);
+        assert_eq!(*counter, 42);
+    }
+
+    #[test]
+    fn static_destructors_run_when_dropped() {
+        link_in_mcjit();
+        initialize_native_target();
+        initialize_native_asm_printer();
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            @state = external global i64
+
+            define void @init() {
+              store i64 1, i64* @state
+              ret void
+            }
+
+            define void @fini() {
+              store i64 2, i64* @state
+              ret void
+            }
+        ").unwrap();
+        module.add_global_ctor(&module.get_named_function("init").unwrap(), 65535);
+        module.add_global_dtor(&module.get_named_function("fini").unwrap(), 65535);
+        let mut ee = ExecutionEngine::create_for_module(
+            context.module_create_with_name("host")).unwrap();
+
+        let mut state = Box::new(0i64);
+        ee.add_global_mapping(module.get_named_global("state").unwrap(),
+                              &mut *state as *mut i64 as *const ());
+        ee.add_module(module);
+        unsafe { ee.run_static_constructors() };
+        assert_eq!(*state, 1);
+        drop(ee);
+        assert_eq!(*state, 2);
+    }
+
+    #[test]
+    fn interpreter_can_run_functions() {
+        link_in_interpreter();
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i32 @mul_add(i32 %x, i32 %y, i32 %z) {
+              %m = mul i32 %x, %y
+              %a = add i32 %m, %z
+              ret i32 %a
+            }
+
+            define double @half(double %x) {
+              %y = fmul double %x, 0.5
+              ret double %y
+            }
+
+            define i32 @main(i32 %argc, i8** %argv) {
+              ret i32 %argc
+            }
+        ").unwrap();
+        let ee = ExecutionEngine::create_interpreter_for_module(module).unwrap();
+        let mul_add = ee.find_function("mul_add").unwrap();
+        let half = ee.find_function("half").unwrap();
+        let main = ee.find_function("main").unwrap();
+        assert!(ee.find_function("missing").is_none());
+
+        let i32_type = context.i32_type();
+        let args = [GenericValue::from_int(i32_type, 6, false),
+                    GenericValue::from_int(i32_type, 7, false),
+                    GenericValue::from_int(i32_type, -2i64 as u64, true)];
+        unsafe {
+            let result = ee.run_function(&mul_add, &args).unwrap();
+            assert_eq!(result.int_width(), 32);
+            assert_eq!(result.as_i64(), 40);
+
+            let result = ee.run_function(&half, &[GenericValue::from_f64(5.0)]).unwrap();
+            assert_eq!(result.as_f64(), 2.5);
+
+            match *ee.run_function(&half, &[]).unwrap_err().kind() {
+                ErrorKind::ArgumentCount(ref name, 1, 0) => assert_eq!(name, "half"),
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+            match *ee.run_function(&half, &[GenericValue::from_f32(5.0)]).unwrap_err().kind() {
+                ErrorKind::ArgumentType(ref name, 0, ref ty) => {
+                    assert_eq!((&name[..], &ty[..]), ("half", "double"))
+                }
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+            let args = [GenericValue::from_int(context.i64_type(), 6, false),
+                        GenericValue::from_int(i32_type, 7, false),
+                        GenericValue::from_int(i32_type, 1, false)];
+            match *ee.run_function(&mul_add, &args).unwrap_err().kind() {
+                ErrorKind::ArgumentType(_, 0, ref ty) => assert_eq!(ty, "i32"),
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+
+            assert_eq!(ee.run_function_as_main(&main, &["prog", "a", "b"], &["A=1"]).unwrap(), 3);
+            match *ee.run_function_as_main(&mul_add, &[], &[]).unwrap_err().kind() {
+                ErrorKind::SignatureMismatch(ref name, _, _) => assert_eq!(name, "mul_add"),
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+
+            let other = context.parse_ir("define i32 @main() {\n  ret i32 0\n}").unwrap();
+            let other_main = other.get_named_function("main").unwrap();
+            match *ee.run_function_as_main(&other_main, &[], &[]).unwrap_err().kind() {
+                ErrorKind::UnknownFunction(ref name) => assert_eq!(name, "main"),
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn can_create_mcjit_compilers_with_options() {
+        link_in_mcjit();
+        initialize_native_target();
+        initialize_native_asm_printer();
+
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i64 @square(i64 %x) {
+              %y = mul i64 %x, %x
+              ret i64 %y
+            }
+        ").unwrap();
+        let options = MCJITCompilerOptions {
+            opt_level: OptLevel::Default,
+            no_frame_pointer_elim: true,
+            fast_isel: true,
+            ..Default::default()
+        };
+        let ee = ExecutionEngine::create_mcjit_compiler_for_module(module, &options).unwrap();
+
+        let square = ee.get_function::<extern "C" fn(i64) -> i64>("square").unwrap();
+        assert_eq!(unsafe { square.call(9) }, 81);
+    }
+
+    #[test]
+    fn can_verify_before_codegen() {
+        link_in_mcjit();
+        initialize_native_target();
+
+        let context = Context::new();
+        let mut module = context.parse_ir("
+            define i32 @f() {
+              %a = add i32 %b, 1
+              %b = add i32 1, 1
+              ret i32 %a
+            }
+        ").unwrap();
+
+        module.set_verify_before_codegen(true);
+        match *ExecutionEngine::create_for_module(module).unwrap_err().kind() {
+            ErrorKind::Module(module::ErrorKind::Verify(ref e)) => {
+                assert_eq!(e.functions, ["f"])
+            }
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// Functions of LLVM's C++ API that its C API lacks in LLVM 14, and that are
+// inline in its headers, so unlike those of `ffi.rs` they can't be linked by
+// their mangled names. Each is bound in `ffi.rs` under the name of its C API
+// counterpart of later LLVM versions.
+
+#include <llvm/IR/Instructions.h>
+#include <llvm-c/Core.h>
+
+using namespace llvm;
+
+extern "C" {
+
+// The kinds are numbered like `CallInst::TailCallKind`
+unsigned llvm_rs_get_tail_call_kind(LLVMValueRef Call) {
+    return unwrap<CallInst>(Call)->getTailCallKind();
+}
+
+void llvm_rs_set_tail_call_kind(LLVMValueRef Call, unsigned Kind) {
+    unwrap<CallInst>(Call)->setTailCallKind(static_cast<CallInst::TailCallKind>(Kind));
+}
+
+}
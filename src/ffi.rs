@@ -0,0 +1,49 @@
+//! Functions of LLVM's C++ API that its C API lacks in LLVM 14, linked by
+//! their Itanium mangled names and named like their C API counterparts of
+//! later LLVM versions.
+//!
+//! These take an `Instruction *` as `this`, which is the same pointer as the
+//! `LLVMValueRef`. The C++ functions cast the instruction to
+//! `FPMathOperator`, `OverflowingBinaryOperator` or `PossiblyExactOperator`
+//! without checking it, so callers must check the opcode first.
+//!
+//! MSVC mangles names differently, so the crate refuses to build for MSVC
+//! targets rather than leave out the API using these.
+//!
+//! Functions that are inline in LLVM's headers have no symbols to link, so
+//! they're wrapped by the C functions of `ffi.cpp` instead, which the build
+//! script compiles against LLVM.
+
+#![allow(non_snake_case)]
+
+use llvm_sys::prelude::*;
+
+extern "C" {
+    // `FastMathFlags` is a class wrapping an `unsigned`, which is passed and
+    // returned like one
+    #[link_name = "_ZNK4llvm11Instruction16getFastMathFlagsEv"]
+    pub fn LLVMGetFastMathFlags(FPMathInst: LLVMValueRef) -> ::libc::c_uint;
+    // Unlike `setFastMathFlags`, which only adds flags, this replaces them
+    #[link_name = "_ZN4llvm11Instruction17copyFastMathFlagsENS_13FastMathFlagsE"]
+    pub fn LLVMSetFastMathFlags(FPMathInst: LLVMValueRef, FMF: ::libc::c_uint);
+
+    // These take and return a C++ `bool` rather than an `LLVMBool`
+    #[link_name = "_ZNK4llvm11Instruction15hasNoSignedWrapEv"]
+    pub fn LLVMGetNSW(ArithInst: LLVMValueRef) -> bool;
+    #[link_name = "_ZN4llvm11Instruction18setHasNoSignedWrapEb"]
+    pub fn LLVMSetNSW(ArithInst: LLVMValueRef, HasNSW: bool);
+    #[link_name = "_ZNK4llvm11Instruction17hasNoUnsignedWrapEv"]
+    pub fn LLVMGetNUW(ArithInst: LLVMValueRef) -> bool;
+    #[link_name = "_ZN4llvm11Instruction20setHasNoUnsignedWrapEb"]
+    pub fn LLVMSetNUW(ArithInst: LLVMValueRef, HasNUW: bool);
+    #[link_name = "_ZNK4llvm11Instruction7isExactEv"]
+    pub fn LLVMGetExact(DivOrShrInst: LLVMValueRef) -> bool;
+    #[link_name = "_ZN4llvm11Instruction10setIsExactEb"]
+    pub fn LLVMSetExact(DivOrShrInst: LLVMValueRef, IsExact: bool);
+
+    // The kinds are numbered like `LLVMTailCallKind` of later versions
+    #[link_name = "llvm_rs_get_tail_call_kind"]
+    pub fn LLVMGetTailCallKind(CallInst: LLVMValueRef) -> ::libc::c_uint;
+    #[link_name = "llvm_rs_set_tail_call_kind"]
+    pub fn LLVMSetTailCallKind(CallInst: LLVMValueRef, Kind: ::libc::c_uint);
+}
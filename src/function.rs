@@ -1,28 +1,72 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
 use llvm_sys::prelude::*;
 use llvm_sys::core::*;
 
-#[derive(Debug)]
-pub struct Function {
+use module::{self, VerifyError};
+use value::{Value, Argument, BasicBlock, GlobalValue};
+
+/// A function in a `Module`. This is a cheap handle that can be copied
+/// freely, and borrows the module it belongs to for `'m`, as do its
+/// parameters, basic blocks and instructions.
+///
+/// ```rust,compile_fail,E0505
+/// let context = llvm::Context::new();
+/// let module = context.module_create_with_name("module");
+/// let ty = llvm::types::Function::new(context.void_type(), &[], false);
+/// let func = module.add_function(ty, "func");
+/// drop(module); // error: cannot move out of `module` because it is borrowed
+/// func.count_basic_blocks();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Function<'m> {
     pub ptr: LLVMValueRef,
+    _marker: PhantomData<Cell<&'m ()>>,
 }
 
-impl Function {
-    pub fn from_value_ref(p: LLVMValueRef) -> Function {
+impl<'m> Function<'m> {
+    /// Wraps a raw function.
+    ///
+    /// # Safety
+    ///
+    /// `p` must be a function whose module outlives `'m`.
+    pub unsafe fn from_value_ref(p: LLVMValueRef) -> Function<'m> {
         Function {
-            ptr: p
+            ptr: p,
+            _marker: PhantomData,
         }
     }
 
-    pub fn params(&self) -> FunctionParamIter {
+    /// Returns this function as a value, e.g. to store its address or to
+    /// call it through a pointer.
+    pub fn as_value(&self) -> &'m Value {
+        self.ptr.into()
+    }
+
+    /// Runs LLVM's verifier on this function, which must be part of a
+    /// module. If it's invalid, the errors of the returned `VerifyError` are
+    /// those of the whole module.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        module::verify_function(self.ptr)
+    }
+
+    pub fn params(&self) -> FunctionParamIter<'m> {
         FunctionParamIter {
             arg: self.ptr,
             first: true,
+            _marker: PhantomData,
         }
     }
 
     // TODO: Check if there is an optimization so that we could
     // call func.params().nth(1) and call this function
-    pub fn get_param(&self, index: u32) -> Option<LLVMValueRef> {
+    pub fn get_param(&self, index: u32) -> Option<&'m Argument> {
+        if index >= self.count_params() {
+            return None;
+        }
+
         let p = unsafe {
             LLVMGetParam(self.ptr, index)
         };
@@ -30,7 +74,13 @@ impl Function {
         if p.is_null() {
             return None;
         } else  {
-            return Some(p);
+            return Some(p.into());
+        }
+    }
+
+    pub fn count_params(&self) -> u32 {
+        unsafe {
+            LLVMCountParams(self.ptr)
         }
     }
 
@@ -39,21 +89,66 @@ impl Function {
             LLVMCountBasicBlocks(self.ptr)
         }
     }
+
+    /// Returns the entry basic block of this function, or `None` if the
+    /// function is only a declaration.
+    pub fn entry_basic_block(&self) -> Option<&'m BasicBlock> {
+        if self.count_basic_blocks() == 0 {
+            None
+        } else {
+            Some(unsafe { LLVMGetEntryBasicBlock(self.ptr).into() })
+        }
+    }
+
+    /// Returns the personality function, which the unwinder calls to find
+    /// the handlers of this function, if it has one.
+    pub fn personality(&self) -> Option<Function<'m>> {
+        unsafe {
+            if LLVMHasPersonalityFn(self.ptr) == 0 {
+                None
+            } else {
+                Some(Function::from_value_ref(LLVMGetPersonalityFn(self.ptr)))
+            }
+        }
+    }
+
+    /// Sets the personality function, e.g. `__gxx_personality_v0` for
+    /// Itanium style `landingpad`s or `__CxxFrameHandler3` for funclets. A
+    /// function handling exceptions must have one.
+    pub fn set_personality(&self, personality: Function<'m>) {
+        unsafe { LLVMSetPersonalityFn(self.ptr, personality.ptr) }
+    }
+}
+
+// Functions are values, e.g. they can be called through or stored.
+impl<'m> AsRef<GlobalValue> for Function<'m> {
+    fn as_ref(&self) -> &GlobalValue {
+        self.ptr.into()
+    }
 }
 
+impl<'m> Deref for Function<'m> {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        self.as_value()
+    }
+}
 
 #[derive(Debug)]
-pub struct FunctionParamIter {
+pub struct FunctionParamIter<'a> {
     arg: LLVMValueRef,
     first: bool,
+    _marker: PhantomData<&'a Argument>,
 }
 
 // TODO: Needs testing
-impl Iterator for FunctionParamIter {
-    type Item = LLVMValueRef;
+impl<'a> Iterator for FunctionParamIter<'a> {
+    type Item = &'a Argument;
 
-    fn next(&mut self) -> Option<LLVMValueRef> {
+    fn next(&mut self) -> Option<&'a Argument> {
         self.arg = if self.first {
+            self.first = false;
             unsafe { LLVMGetFirstParam(self.arg) }
         } else {
             unsafe { LLVMGetNextParam(self.arg) }
@@ -62,7 +157,7 @@ impl Iterator for FunctionParamIter {
         if self.arg.is_null() {
             None
         } else {
-            Some(self.arg)
+            Some(self.arg.into())
         }
     }
 }
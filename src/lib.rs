@@ -23,25 +23,48 @@ macro_rules! c_str_to_str {
     }
 }
 
+// Converts a message allocated by LLVM into a `String`, disposing of it
+unsafe fn take_message(message: *mut libc::c_char) -> String {
+    let s = CStr::from_ptr(message).to_string_lossy().into_owned();
+    llvm_sys::core::LLVMDisposeMessage(message);
+    s
+}
+
 #[macro_use]
 mod macros;
 mod context;
 pub mod types;
 mod builder;
-mod module;
+pub mod module;
 mod function;
 mod pass_manager;
-mod target;
-mod execution_engine;
-mod value;
+pub mod target;
+pub mod execution_engine;
+pub mod value;
+pub mod memory_buffer;
+pub mod enums;
+pub mod orc;
+mod ffi;
+
+// Fast math flags are accessed through C++ functions that `ffi` links by
+// their Itanium mangled names, which MSVC doesn't use
+#[cfg(target_env = "msvc")]
+compile_error!("MSVC targets aren't supported, as fast math flags are accessed through \
+                LLVM's C++ API by Itanium mangled names");
 
 // TODO: This was to maintain compatiblity, we should remove this
 pub use context::*;
 pub use types::{Type, ContextType};
 pub use builder::*;
-pub use module::*;
+pub use module::{Module, ParseDiagnostic, VerifyError};
 pub use function::*;
 pub use pass_manager::*;
-pub use target::*;
-pub use execution_engine::*;
-pub use value::*;
+pub use target::{Target, TargetMachine, get_default_target_triple, initialize_native_target,
+                 initialize_native_asm_printer};
+pub use execution_engine::{ExecutionEngine, JitType, JitReturnType, JitSignature, JitFunction,
+                           MCJITCompilerOptions, GenericValue, ModuleKey, link_in_mcjit,
+                           link_in_interpreter};
+pub use value::{Value, IntoConstValue};
+pub use memory_buffer::MemoryBuffer;
+pub use enums::{IntPredicate, RealPredicate, Opcode, BinaryOp, UnaryOp, BinaryOpFlag,
+                FastMathFlags, AtomicOrdering, AtomicRMWBinOp, CallConv, OptLevel, RelocMode, CodeModel, FileType};
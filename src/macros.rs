@@ -1,5 +1,24 @@
 // TODO: This could be named better
 macro_rules! impl_llvm_ref {
+    ($dest: ident<$lt: lifetime>, $ref: ty) => {
+        impl<$lt> From<$ref> for $dest<$lt> {
+            fn from(ptr: $ref) -> Self {
+                $dest {
+                    ptr: ptr,
+                    _marker: ::std::marker::PhantomData,
+                }
+            }
+        }
+
+        impl<$lt> From<$dest<$lt>> for $ref {
+            fn from(s: $dest<$lt>) -> Self {
+                // The caller takes over ownership of the pointer
+                let ptr = s.ptr;
+                ::std::mem::forget(s);
+                ptr
+            }
+        }
+    };
     ($dest: tt, $ref: ty) => {
         impl From<$ref> for $dest {
             fn from(ptr: $ref) -> Self {
@@ -11,7 +30,10 @@ macro_rules! impl_llvm_ref {
 
         impl From<$dest> for $ref {
             fn from(s: $dest) -> Self {
-                s.ptr
+                // The caller takes over ownership of the pointer
+                let ptr = s.ptr;
+                ::std::mem::forget(s);
+                ptr
             }
         }
     }
@@ -0,0 +1,204 @@
+//! LLVM memory buffers.
+//!
+//! A [`MemoryBuffer`] is a read only block of memory, used by LLVM as the
+//! input of its parsers and as the output of its writers.
+//!
+//! [`MemoryBuffer`]: struct.MemoryBuffer.html
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use llvm_sys::prelude::*;
+use llvm_sys::core as llvm;
+
+use super::*;
+
+error_chain! {
+    errors {
+        ReadFile(path: String, t: String) {
+            description("Error while reading file into a memory buffer")
+            display("Error while reading '{}' into a memory buffer: '{}'", path, t)
+        }
+        ReadStdin(t: String) {
+            description("Error while reading stdin into a memory buffer")
+            display("Error while reading stdin into a memory buffer: '{}'", t)
+        }
+    }
+}
+
+/// A read only block of memory.
+///
+/// Buffers either own their contents, or borrow them from a `&'a [u8]` for
+/// the `'a` lifetime, so a buffer can't outlive the bytes it points to:
+///
+/// ```rust,compile_fail,E0597
+/// use llvm::MemoryBuffer;
+///
+/// let buffer = {
+///     let bytes = vec![1, 2, 3];
+///     MemoryBuffer::from_slice(&bytes, "bytes")
+/// }; // error: `bytes` does not live long enough
+/// ```
+pub struct MemoryBuffer<'a> {
+    pub ptr: LLVMMemoryBufferRef,
+    // The name of a borrowed buffer, whose contents may lack the following
+    // null byte the IR parser relies on. It names the copy made for parsing.
+    borrowed_name: Option<String>,
+    _marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> MemoryBuffer<'a> {
+    /// Reads the contents of the file at `path` into a new buffer.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<MemoryBuffer<'static>> {
+        let str_path = path.as_ref().to_str().expect("Failed to convert path to unicode");
+        let c_path = CString::new(str_path).unwrap();
+        unsafe {
+            let mut ptr = ::std::ptr::null_mut();
+            let mut message = ::std::ptr::null_mut();
+            if llvm::LLVMCreateMemoryBufferWithContentsOfFile(c_path.as_ptr(),
+                                                              &mut ptr,
+                                                              &mut message) == 0 {
+                Ok(ptr.into())
+            } else {
+                let message = take_message(message);
+                Err(ErrorKind::ReadFile(str_path.into(), message).into())
+            }
+        }
+    }
+
+    /// Reads all of stdin into a new buffer.
+    pub fn from_stdin() -> Result<MemoryBuffer<'static>> {
+        unsafe {
+            let mut ptr = ::std::ptr::null_mut();
+            let mut message = ::std::ptr::null_mut();
+            if llvm::LLVMCreateMemoryBufferWithSTDIN(&mut ptr, &mut message) == 0 {
+                Ok(ptr.into())
+            } else {
+                Err(ErrorKind::ReadStdin(take_message(message)).into())
+            }
+        }
+    }
+
+    /// Creates a new buffer borrowing `bytes`, without copying them. The
+    /// `name` of the buffer shows up in diagnostics, e.g. as the file name of
+    /// parse errors.
+    ///
+    /// ```rust
+    /// use llvm::MemoryBuffer;
+    ///
+    /// let bytes = b"some bytes";
+    /// let buffer = MemoryBuffer::from_slice(bytes, "bytes");
+    /// assert_eq!(buffer.as_bytes(), bytes);
+    /// ```
+    pub fn from_slice(bytes: &'a [u8], name: &str) -> MemoryBuffer<'a> {
+        let c_name = CString::new(name).unwrap();
+        let ptr = unsafe {
+            llvm::LLVMCreateMemoryBufferWithMemoryRange(bytes.as_ptr() as *const _,
+                                                        bytes.len(),
+                                                        c_name.as_ptr(),
+                                                        0)
+        };
+        MemoryBuffer {
+            ptr: ptr,
+            borrowed_name: Some(name.into()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a new buffer holding a copy of `bytes`. The `name` of the
+    /// buffer shows up in diagnostics, e.g. as the file name of parse errors.
+    pub fn from_slice_copy(bytes: &[u8], name: &str) -> MemoryBuffer<'static> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            llvm::LLVMCreateMemoryBufferWithMemoryRangeCopy(bytes.as_ptr() as *const _,
+                                                            bytes.len(),
+                                                            c_name.as_ptr()).into()
+        }
+    }
+
+    /// Returns the contents of the buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let start = llvm::LLVMGetBufferStart(self.ptr) as *const u8;
+            let size = llvm::LLVMGetBufferSize(self.ptr);
+            if size == 0 {
+                &[]
+            } else {
+                ::std::slice::from_raw_parts(start, size)
+            }
+        }
+    }
+
+    /// Returns a buffer whose contents are followed by a null byte, copying
+    /// the contents, under the same name, if this one doesn't guarantee it.
+    pub(crate) fn null_terminated(self) -> MemoryBuffer<'a> {
+        match self.borrowed_name {
+            Some(ref name) => MemoryBuffer::from_slice_copy(self.as_bytes(), name),
+            None => self,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for MemoryBuffer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::MemoryBuffer({:?})", self.ptr)
+    }
+}
+
+// Buffers created by LLVM own their contents, which are always followed by a
+// null byte
+impl From<LLVMMemoryBufferRef> for MemoryBuffer<'static> {
+    fn from(ptr: LLVMMemoryBufferRef) -> Self {
+        MemoryBuffer {
+            ptr: ptr,
+            borrowed_name: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a> From<MemoryBuffer<'a>> for LLVMMemoryBufferRef {
+    fn from(s: MemoryBuffer<'a>) -> Self {
+        // The caller takes over ownership of the pointer
+        let ptr = s.ptr;
+        ::std::mem::forget(s);
+        ptr
+    }
+}
+
+impl<'a> Drop for MemoryBuffer<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposeMemoryBuffer(self.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_copy_slices() {
+        let buffer = {
+            let bytes = vec![1, 2, 3];
+            MemoryBuffer::from_slice_copy(&bytes, "bytes")
+        };
+        assert_eq!(buffer.as_bytes(), &[1, 2, 3]);
+        assert!(MemoryBuffer::from_slice_copy(&[], "empty").as_bytes().is_empty());
+    }
+
+    #[test]
+    fn can_parse_ir_from_borrowed_slices() {
+        let context = Context::new();
+        let ir = String::from("@answer = global i32 42");
+        let buffer = MemoryBuffer::from_slice(ir.as_bytes(), "answer");
+        assert_eq!(buffer.as_bytes(), ir.as_bytes());
+
+        let module = Module::parse_ir(&context, buffer).unwrap();
+        let ir = module.to_string();
+        assert!(ir.contains("@answer"), "{}", ir);
+        assert!(ir.contains("; ModuleID = 'answer'"), "{}", ir);
+    }
+}
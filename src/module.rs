@@ -1,48 +1,448 @@
+use std::error;
 use std::fmt;
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
 
-use libc;
+use libc::{self, c_void};
 
 use llvm_sys::prelude::*;
 use llvm_sys::core as llvm;
+use llvm_sys::bit_reader::*;
+use llvm_sys::bit_writer::*;
+use llvm_sys::analysis::*;
+use llvm_sys::error::*;
+use llvm_sys::transforms::pass_builder::LLVMRunPasses;
+use llvm_sys::ir_reader::LLVMParseIRInContext;
+use llvm_sys::{LLVMDiagnosticSeverity, LLVMLinkage, LLVMTypeKind};
 
 use super::*;
 
 use std::path::Path;
 
 error_chain! {
+    links {
+        MemoryBuffer(memory_buffer::Error, memory_buffer::ErrorKind);
+    }
+
     errors {
         ModulePrintFile(t: String) {
             description("Error while printing module to file")
             display("Error while printing module to file: '{}'", t)
         }
+        ParseIR(diagnostic: ParseDiagnostic) {
+            description("Error while parsing IR")
+            display("Error while parsing IR: {}", diagnostic)
+        }
+        ParseBitcode(t: String) {
+            description("Error while parsing bitcode")
+            display("Error while parsing bitcode: '{}'", t)
+        }
+        WriteBitcodeFile(path: String) {
+            description("Error while writing bitcode to file")
+            display("Error while writing bitcode to file '{}'", path)
+        }
+        InvalidPassPipeline(pipeline: String, t: String) {
+            description("Error while running pass pipeline")
+            display("Error while running pass pipeline '{}': '{}'", pipeline, t)
+        }
+        Verify(error: VerifyError) {
+            description("Module is invalid")
+            display("Module is invalid: {}", error)
+        }
+    }
+}
+
+/// The error reported by LLVM's parser, pointing at the location of the
+/// first error in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    /// The 1-based line of the error, or 0 if it's unknown
+    pub line: u32,
+    /// The 1-based column of the error, or 0 if it's unknown
+    pub column: u32,
+    pub message: String,
+}
+
+impl ParseDiagnostic {
+    // Parses a diagnostic printed by LLVM, e.g.
+    // `<string>:1:8: error: expected type`, followed by the source line and
+    // a caret pointing at the column.
+    fn from_message(message: &str) -> ParseDiagnostic {
+        let first_line = message.lines().next().unwrap_or("");
+        if let Some(i) = first_line.find(": error: ") {
+            let mut location = first_line[..i].rsplitn(3, ':');
+            let column = location.next().and_then(|c| c.parse().ok());
+            let line = location.next().and_then(|l| l.parse().ok());
+            if let (Some(line), Some(column)) = (line, column) {
+                return ParseDiagnostic {
+                    line: line,
+                    column: column,
+                    message: first_line[i + ": error: ".len()..].into(),
+                };
+            }
+        }
+
+        ParseDiagnostic {
+            line: 0,
+            column: 0,
+            message: message.trim().into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// The errors found by LLVM's verifier in a module or function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The names of the invalid functions, in the order of the module
+    pub functions: Vec<String>,
+    /// The verifier's errors, each a line followed by the indented values it
+    /// is about. The C API only reports these for a whole module, so they
+    /// aren't attributed to functions, and include the errors of other
+    /// functions and of globals or metadata.
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.errors.join("\n"))
+    }
+}
+
+impl error::Error for VerifyError {
+    fn description(&self) -> &str {
+        "Module is invalid"
+    }
+}
+
+// Returns the functions of `module`, in order
+pub(crate) unsafe fn functions(module: LLVMModuleRef) -> Vec<LLVMValueRef> {
+    let mut functions = Vec::new();
+    let mut function = llvm::LLVMGetFirstFunction(module);
+    while !function.is_null() {
+        functions.push(function);
+        function = llvm::LLVMGetNextFunction(function);
+    }
+    functions
+}
+
+// Runs the verifier on `module`, returning its messages if it's broken
+unsafe fn verify_module(module: LLVMModuleRef) -> Option<String> {
+    let mut message = ::std::ptr::null_mut();
+    let broken = LLVMVerifyModule(module, LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                                  &mut message) != 0;
+    let message = take_message(message);
+    if broken {
+        Some(message.trim_end().into())
+    } else {
+        None
+    }
+}
+
+// Splits the verifier's messages into its errors, each of which is a line
+// followed by the indented instructions or values it is about
+fn split_errors(message: &str) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+    for line in message.lines() {
+        match errors.last_mut() {
+            Some(error) if line.starts_with(' ') => {
+                error.push('\n');
+                error.push_str(line);
+            }
+            _ => errors.push(line.into()),
+        }
+    }
+    errors
+}
+
+// Returns whether `function`, which is part of a module, is invalid. Unlike
+// verifying its module, this only checks the function itself.
+unsafe fn is_broken(function: LLVMValueRef) -> bool {
+    llvm::LLVMIsDeclaration(function) == 0 &&
+        LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMReturnStatusAction) != 0
+}
+
+// Verifies `function`, which is part of a module
+pub(crate) fn verify_function(function: LLVMValueRef) -> ::std::result::Result<(), VerifyError> {
+    unsafe {
+        if !is_broken(function) {
+            return Ok(());
+        }
+        let message = verify_module(llvm::LLVMGetGlobalParent(function)).unwrap_or_default();
+        Err(VerifyError {
+            functions: vec![<&Value>::from(function).name()],
+            errors: split_errors(&message),
+        })
     }
 }
 
-// No `Drop` impl is needed as this is disposed of when the associated context is disposed
+/// An LLVM module, which owns the functions and globals added to it.
+///
+/// The module is disposed of when it's dropped, unless its ownership was
+/// handed over to someone else, e.g. with `ExecutionEngine::create_for_module`.
 #[derive(Debug)]
-pub struct Module {
-    pub ptr: LLVMModuleRef
+pub struct Module<'ctx> {
+    pub ptr: LLVMModuleRef,
+    pub(crate) verify_before_codegen: bool,
+    // Globals replaced by `add_global_ctor` and `add_global_dtor`, which are
+    // only deleted once no handle into the module can be left
+    retired_globals: RefCell<Vec<LLVMValueRef>>,
+    _marker: PhantomData<Cell<&'ctx ContextRef>>,
 }
-impl_llvm_ref!(Module, LLVMModuleRef);
 
-impl Module {
+impl<'ctx> From<LLVMModuleRef> for Module<'ctx> {
+    fn from(ptr: LLVMModuleRef) -> Self {
+        Module {
+            ptr: ptr,
+            verify_before_codegen: false,
+            retired_globals: RefCell::new(Vec::new()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'ctx> From<Module<'ctx>> for LLVMModuleRef {
+    fn from(mut module: Module<'ctx>) -> Self {
+        // The caller takes over ownership of the pointer
+        module.delete_retired_globals();
+        let ptr = module.ptr;
+        ::std::mem::forget(module);
+        ptr
+    }
+}
+
+// Runs `f` with a diagnostic handler installed on `context` that collects
+// error messages. LLVM's default handler exits the process on errors, which
+// is what the bitcode reader reports its errors through.
+fn collect_errors<T, F: FnOnce() -> T>(context: &ContextRef, f: F) -> (T, Vec<String>) {
+    extern "C" fn handler(info: LLVMDiagnosticInfoRef, errors: *mut c_void) {
+        unsafe {
+            if let LLVMDiagnosticSeverity::LLVMDSError = llvm::LLVMGetDiagInfoSeverity(info) {
+                let errors = &mut *(errors as *mut Vec<String>);
+                errors.push(take_message(llvm::LLVMGetDiagInfoDescription(info)));
+            }
+        }
+    }
+
+    let mut errors: Vec<String> = Vec::new();
+    unsafe {
+        let old_handler = llvm::LLVMContextGetDiagnosticHandler(context.into());
+        let old_context = llvm::LLVMContextGetDiagnosticContext(context.into());
+        llvm::LLVMContextSetDiagnosticHandler(context.into(),
+                                              Some(handler),
+                                              &mut errors as *mut Vec<String> as *mut c_void);
+        let res = f();
+        llvm::LLVMContextSetDiagnosticHandler(context.into(), old_handler, old_context);
+        (res, errors)
+    }
+}
+
+impl<'ctx> Module<'ctx> {
+    /// Parses the textual IR in `buffer` into a new module.
+    pub fn parse_ir(context: &'ctx ContextRef, buffer: MemoryBuffer) -> Result<Module<'ctx>> {
+        // The IR lexer reads up to the null byte following the contents
+        let buffer = buffer.null_terminated();
+        unsafe {
+            let mut module = ::std::ptr::null_mut();
+            let mut message = ::std::ptr::null_mut();
+            // This takes ownership of the buffer
+            if LLVMParseIRInContext(context.into(), buffer.into(),
+                                    &mut module, &mut message) == 0 {
+                Ok(module.into())
+            } else {
+                let diagnostic = ParseDiagnostic::from_message(&take_message(message));
+                Err(ErrorKind::ParseIR(diagnostic).into())
+            }
+        }
+    }
+
+    /// Parses the textual IR file at `path` into a new module.
+    ///
+    /// ```rust
+    /// use llvm::{Context, Module};
+    ///
+    /// let context = Context::new();
+    /// let path = "./parse_ir_file.ll";
+    /// std::fs::write(path, "define i32 @zero() {\n  ret i32 0\n}\n").unwrap();
+    ///
+    /// let module = Module::parse_ir_file(&context, path).unwrap();
+    /// assert!(module.to_string().contains("@zero"));
+    /// std::fs::remove_file(path).unwrap()
+    /// ```
+    pub fn parse_ir_file<P: AsRef<Path>>(context: &'ctx ContextRef, path: P) -> Result<Module<'ctx>> {
+        let buffer = MemoryBuffer::from_file(path)?;
+        Module::parse_ir(context, buffer)
+    }
+
+    /// Parses the bitcode in `buffer` into a new module.
+    pub fn parse_bitcode(context: &'ctx ContextRef, buffer: &MemoryBuffer) -> Result<Module<'ctx>> {
+        let mut module = ::std::ptr::null_mut();
+        let (res, errors) = collect_errors(context, || unsafe {
+            LLVMParseBitcodeInContext2(context.into(), buffer.ptr, &mut module)
+        });
+
+        if res == 0 {
+            Ok(module.into())
+        } else {
+            Err(ErrorKind::ParseBitcode(errors.join("\n")).into())
+        }
+    }
+
+    /// Parses the bitcode file at `path` into a new module.
+    pub fn parse_bitcode_file<P: AsRef<Path>>(context: &'ctx ContextRef, path: P) -> Result<Module<'ctx>> {
+        let buffer = MemoryBuffer::from_file(path)?;
+        Module::parse_bitcode(context, &buffer)
+    }
+
+    /// Lazily loads the bitcode in `buffer` into a new module, which takes
+    /// ownership of the buffer.
+    ///
+    /// Only the module's globals and function declarations are read
+    /// upfront; function bodies are read from the buffer when they're first
+    /// needed, so the buffer must outlive the module.
+    pub fn lazy_load_bitcode(context: &'ctx ContextRef, buffer: MemoryBuffer<'ctx>) -> Result<Module<'ctx>> {
+        let mut module = ::std::ptr::null_mut();
+        // This takes ownership of the buffer
+        let buffer_ptr: LLVMMemoryBufferRef = buffer.into();
+        let (res, errors) = collect_errors(context, || unsafe {
+            LLVMGetBitcodeModuleInContext2(context.into(), buffer_ptr, &mut module)
+        });
+
+        if res == 0 {
+            Ok(module.into())
+        } else {
+            Err(ErrorKind::ParseBitcode(errors.join("\n")).into())
+        }
+    }
+
+    /// Runs LLVM's verifier on this module, returning the errors it found in
+    /// each function and in the module itself.
+    ///
+    /// ```rust
+    /// use llvm::{types, Context};
+    ///
+    /// let context = Context::new();
+    /// let module = context.module_create_with_name("module");
+    /// let ty = types::Function::new(context.void_type(), &[], false);
+    /// let mut func = module.add_function(ty, "f");
+    /// // A basic block without a terminator
+    /// context.append_basic_block(&mut func, "entry");
+    ///
+    /// let error = module.verify().unwrap_err();
+    /// assert_eq!(error.functions, ["f"]);
+    /// assert!(error.errors[0].contains("does not have terminator"));
+    /// ```
+    pub fn verify(&self) -> ::std::result::Result<(), VerifyError> {
+        unsafe {
+            let message = match verify_module(self.ptr) {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+
+            let functions = functions(self.ptr).into_iter()
+                .filter(|&function| is_broken(function))
+                .map(|function| <&Value>::from(function).name())
+                .collect();
+            Err(VerifyError { functions: functions, errors: split_errors(&message) })
+        }
+    }
+
+    /// Runs the passes described by `pipeline` on this module, in the
+    /// syntax of `opt -passes=...`, e.g. `"default<O2>"` or
+    /// `"function(instcombine,gvn),inline"`.
+    ///
+    /// Passes can only use information about the target if a
+    /// `target_machine` is given.
+    ///
+    /// ```rust
+    /// use llvm::{Context, PassBuilderOptions};
+    ///
+    /// let context = Context::new();
+    /// let mut module = context.parse_ir("
+    ///     define i32 @f(i32 %x) {
+    ///       %y = add i32 %x, 0
+    ///       ret i32 %y
+    ///     }
+    /// ").unwrap();
+    ///
+    /// let options = PassBuilderOptions::new();
+    /// module.run_passes("function(instcombine)", None, &options).unwrap();
+    /// assert!(module.to_string().contains("ret i32 %x"));
+    /// ```
+    pub fn run_passes(&mut self,
+                      pipeline: &str,
+                      target_machine: Option<&TargetMachine>,
+                      options: &PassBuilderOptions) -> Result<()> {
+        let c_pipeline = CString::new(pipeline).map_err(|_| {
+            ErrorKind::InvalidPassPipeline(pipeline.into(), "pipeline contains a NUL byte".into())
+        })?;
+        let target_machine = target_machine.map_or(::std::ptr::null_mut(), |tm| tm.ptr);
+        // Passes may delete them as well
+        self.delete_retired_globals();
+        unsafe {
+            let error = LLVMRunPasses(self.ptr, c_pipeline.as_ptr(), target_machine, options.ptr);
+            if error.is_null() {
+                Ok(())
+            } else {
+                let message = LLVMGetErrorMessage(error);
+                let t = CStr::from_ptr(message).to_string_lossy().into_owned();
+                LLVMDisposeErrorMessage(message);
+                Err(ErrorKind::InvalidPassPipeline(pipeline.into(), t).into())
+            }
+        }
+    }
+
+    /// Enables or disables verifying this module before it's compiled.
+    ///
+    /// LLVM doesn't check the IR it compiles, and usually crashes the process
+    /// on invalid IR. This is a debugging aid: when enabled, the
+    /// `ExecutionEngine` constructors, `TargetMachine::emit_to_file` and
+    /// `LLJIT::add_module` run the verifier first, and return its errors
+    /// instead of compiling the module. It's disabled by default.
+    pub fn set_verify_before_codegen(&mut self, enabled: bool) {
+        self.verify_before_codegen = enabled;
+    }
+
+    // Verifies this module if verifying before codegen is enabled
+    pub(crate) fn verify_before_codegen(&self) -> Result<()> {
+        if self.verify_before_codegen {
+            self.verify().map_err(|e| ErrorKind::Verify(e).into())
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn dump(&self) {
         unsafe {
             llvm::LLVMDumpModule(self.ptr)
         }
     }
 
-    pub fn add_function(&mut self, func_ty: &types::Function, name: &str) -> Function {
+    /// Adds a function of type `func_ty` to this module. The returned handle
+    /// borrows the module, so it can't outlive it or be used once the module
+    /// is moved into an `ExecutionEngine`.
+    pub fn add_function(&self, func_ty: &'ctx types::Function, name: &str) -> Function<'_> {
         let c_name = CString::new(name).unwrap();
-        let p = unsafe {
-            llvm::LLVMAddFunction(self.ptr, c_name.as_ptr(), func_ty.into())
-        };
-        Function {
-            ptr: p
+        unsafe {
+            Function::from_value_ref(llvm::LLVMAddFunction(self.ptr, c_name.as_ptr(), func_ty.into()))
+        }
+    }
+
+    /// Adds a global variable of type `ty` to this module. The global has no
+    /// initializer, use `GlobalVariable::set_initializer` to define it.
+    pub fn add_global(&self, ty: &'ctx Type, name: &str) -> &value::GlobalVariable {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            llvm::LLVMAddGlobal(self.ptr, ty.into(), c_name.as_ptr()).into()
         }
     }
 
-    pub fn get_named_function(&mut self, name: &str) -> Option<Function> {
+    pub fn get_named_function(&self, name: &str) -> Option<Function<'_>> {
         let c_name = CString::new(name).unwrap();
         let res = unsafe {
             llvm::LLVMGetNamedFunction(self.ptr, c_name.as_ptr())
@@ -51,7 +451,158 @@ impl Module {
         if res.is_null() {
             None
         } else {
-            Some(Function::from_value_ref(res))
+            Some(unsafe { Function::from_value_ref(res) })
+        }
+    }
+
+    /// Returns the global variable `name` of this module, if it exists.
+    pub fn get_named_global(&self, name: &str) -> Option<&value::GlobalVariable> {
+        let c_name = CString::new(name).unwrap();
+        let res = unsafe {
+            llvm::LLVMGetNamedGlobal(self.ptr, c_name.as_ptr())
+        };
+
+        if res.is_null() {
+            None
+        } else {
+            Some(res.into())
+        }
+    }
+
+    /// Registers `function` of this module as a static constructor, which
+    /// runs before `main`, or when `ExecutionEngine::run_static_constructors`
+    /// is called. Constructors with a lower `priority` run first.
+    ///
+    /// The `llvm.global_ctors` array is replaced by a new one with the added
+    /// entry. The old array stays in the module as an unnamed private
+    /// global, so that handles to it remain valid, until the module is
+    /// handed over, e.g. to an `ExecutionEngine`, or `run_passes` is called.
+    /// An existing array with the legacy `{ i32, void ()* }` entries is
+    /// converted to entries with the third, associated data field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `function` doesn't take no arguments and return `void`, or
+    /// if the module already has an `llvm.global_ctors` array of another
+    /// type.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    ///
+    /// let context = Context::new();
+    /// let module = context.module_create_with_name("module");
+    /// let ty = types::Function::new(context.void_type(), &[], false);
+    /// let init = module.add_function(ty, "init");
+    /// module.add_global_ctor(&init, 65535);
+    ///
+    /// assert!(module.to_string().contains(
+    ///     "@llvm.global_ctors = appending global [1 x { i32, void ()*, i8* }] \
+    ///      [{ i32, void ()*, i8* } { i32 65535, void ()* @init, i8* null }]"));
+    /// ```
+    pub fn add_global_ctor(&self, function: &Function<'_>, priority: u32) {
+        self.append_to_global_array("llvm.global_ctors", function, priority);
+    }
+
+    /// Registers `function` of this module as a static destructor, which
+    /// runs after `main` returns, or when
+    /// `ExecutionEngine::run_static_destructors` is called. Destructors with
+    /// a lower `priority` run first.
+    ///
+    /// The `llvm.global_dtors` array is replaced like the constructors of
+    /// `add_global_ctor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `add_global_ctor`, for the `llvm.global_dtors` array.
+    pub fn add_global_dtor(&self, function: &Function<'_>, priority: u32) {
+        self.append_to_global_array("llvm.global_dtors", function, priority);
+    }
+
+    // Appends a `{ priority, function, null }` entry to `llvm.global_ctors`
+    // or `llvm.global_dtors`. The entries of these arrays can't be changed in
+    // place, so the array is replaced with a new one, converting legacy
+    // `{ priority, function }` entries.
+    fn append_to_global_array(&self, array_name: &str, function: &Function<'_>, priority: u32) {
+        let function = function.ptr;
+        unsafe {
+            assert!(llvm::LLVMGetGlobalParent(function) == self.ptr,
+                    "static constructors and destructors must be functions of the module");
+            let ty = llvm::LLVMGlobalGetValueType(function);
+            assert!(llvm::LLVMCountParamTypes(ty) == 0 &&
+                    llvm::LLVMGetTypeKind(llvm::LLVMGetReturnType(ty)) ==
+                    LLVMTypeKind::LLVMVoidTypeKind,
+                    "static constructors and destructors must have type void ()");
+
+            let context = llvm::LLVMGetModuleContext(self.ptr);
+            let i8_ptr = llvm::LLVMPointerType(llvm::LLVMInt8TypeInContext(context), 0);
+            let mut fields = [llvm::LLVMInt32TypeInContext(context),
+                              llvm::LLVMTypeOf(function),
+                              i8_ptr];
+            let entry_ty = llvm::LLVMStructTypeInContext(context,
+                                                         fields.as_mut_ptr(),
+                                                         fields.len() as u32,
+                                                         0);
+            let mut values = [llvm::LLVMConstInt(fields[0], priority.into(), 0),
+                              function,
+                              llvm::LLVMConstNull(i8_ptr)];
+            let entry = llvm::LLVMConstNamedStruct(entry_ty,
+                                                   values.as_mut_ptr(),
+                                                   values.len() as u32);
+
+            let c_name = CString::new(array_name).unwrap();
+            let mut entries = Vec::new();
+            let old = llvm::LLVMGetNamedGlobal(self.ptr, c_name.as_ptr());
+            if !old.is_null() {
+                let old_ty = llvm::LLVMGetElementType(llvm::LLVMGlobalGetValueType(old));
+                let legacy = old_ty != entry_ty &&
+                    llvm::LLVMGetTypeKind(old_ty) == LLVMTypeKind::LLVMStructTypeKind &&
+                    llvm::LLVMCountStructElementTypes(old_ty) == 2 &&
+                    llvm::LLVMStructGetTypeAtIndex(old_ty, 0) == fields[0] &&
+                    llvm::LLVMStructGetTypeAtIndex(old_ty, 1) == fields[1];
+                if old_ty != entry_ty && !legacy {
+                    let old_ty: &Type = old_ty.into();
+                    panic!("{} has entries of type {}, not {{ i32, void ()*, i8* }}",
+                           array_name, old_ty);
+                }
+
+                let initializer = llvm::LLVMGetInitializer(old);
+                if !initializer.is_null() {
+                    for i in 0..llvm::LLVMGetNumOperands(initializer) {
+                        let old_entry = llvm::LLVMGetOperand(initializer, i as u32);
+                        if !legacy {
+                            entries.push(old_entry);
+                        } else if llvm::LLVMIsAConstantStruct(old_entry).is_null() {
+                            // A `zeroinitializer` entry
+                            entries.push(llvm::LLVMConstNull(entry_ty));
+                        } else {
+                            let mut values = [llvm::LLVMGetOperand(old_entry, 0),
+                                              llvm::LLVMGetOperand(old_entry, 1),
+                                              llvm::LLVMConstNull(i8_ptr)];
+                            entries.push(llvm::LLVMConstNamedStruct(entry_ty,
+                                                                    values.as_mut_ptr(),
+                                                                    values.len() as u32));
+                        }
+                    }
+                }
+                // Handles to the old array may still be around, so it's only
+                // made inert here
+                llvm::LLVMSetValueName2(old, b"\0".as_ptr() as *const _, 0);
+                llvm::LLVMSetLinkage(old, LLVMLinkage::LLVMPrivateLinkage);
+                llvm::LLVMSetInitializer(old, llvm::LLVMConstNull(llvm::LLVMGlobalGetValueType(old)));
+                self.retired_globals.borrow_mut().push(old);
+            }
+            entries.push(entry);
+
+            let array = llvm::LLVMConstArray(entry_ty, entries.as_mut_ptr(), entries.len() as u32);
+            let global = llvm::LLVMAddGlobal(self.ptr, llvm::LLVMTypeOf(array), c_name.as_ptr());
+            llvm::LLVMSetInitializer(global, array);
+            llvm::LLVMSetLinkage(global, LLVMLinkage::LLVMAppendingLinkage);
+        }
+    }
+
+    fn delete_retired_globals(&mut self) {
+        for global in ::std::mem::take(self.retired_globals.get_mut()) {
+            unsafe { llvm::LLVMDeleteGlobal(global) };
         }
     }
 
@@ -83,9 +634,41 @@ impl Module {
             }
         }
     }
+
+    /// Writes the bitcode of this module to a file.
+    ///
+    /// ```rust
+    /// use llvm::{Context, Module};
+    ///
+    /// let context = Context::new();
+    /// let module = context.module_create_with_name("name");
+    /// let path = "./module_file.bc";
+    ///
+    /// module.write_bitcode_to_file(path).unwrap();
+    /// let module = Module::parse_bitcode_file(&context, path).unwrap();
+    ///
+    /// std::fs::remove_file(path).unwrap()
+    /// ```
+    pub fn write_bitcode_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let str_path = path.as_ref().to_str().expect("Failed to convert path to unicode");
+        let c_path = CString::new(str_path).unwrap();
+        unsafe {
+            if LLVMWriteBitcodeToFile(self.ptr, c_path.as_ptr()) == 0 {
+                Ok(())
+            } else {
+                Err(ErrorKind::WriteBitcodeFile(str_path.into()).into())
+            }
+        }
+    }
+
+    /// Writes the bitcode of this module into memory.
+    pub fn write_bitcode_to_memory(&self) -> Vec<u8> {
+        let buffer: MemoryBuffer = unsafe { LLVMWriteBitcodeToMemoryBuffer(self.ptr).into() };
+        buffer.as_bytes().to_vec()
+    }
 }
 
-impl fmt::Display for Module {
+impl<'ctx> fmt::Display for Module<'ctx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         unsafe {
             let c_str = llvm::LLVMPrintModuleToString(self.ptr);
@@ -99,3 +682,239 @@ impl fmt::Display for Module {
         }
     }
 }
+
+impl<'ctx> Drop for Module<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposeModule(self.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_ir() {
+        let context = Context::new();
+        let module = context.parse_ir("define i32 @zero() {\n  ret i32 0\n}\n").unwrap();
+        assert!(module.get_named_function("zero").is_some());
+    }
+
+    #[test]
+    fn can_round_trip_bitcode() {
+        let context = Context::new();
+        let module = context.parse_ir("define i32 @zero() {\n  ret i32 0\n}\n").unwrap();
+        let bitcode = module.write_bitcode_to_memory();
+
+        let parsed = context.parse_bitcode(&bitcode).unwrap();
+        assert!(parsed.get_named_function("zero").is_some());
+
+        let buffer = MemoryBuffer::from_slice_copy(&bitcode, "lazy");
+        let lazy = Module::lazy_load_bitcode(&context, buffer).unwrap();
+        assert!(lazy.get_named_function("zero").is_some());
+    }
+
+    #[test]
+    fn invalid_bitcode_is_an_error() {
+        let context = Context::new();
+        match *context.parse_bitcode(b"not bitcode").unwrap_err().kind() {
+            ErrorKind::ParseBitcode(ref message) => assert!(!message.is_empty()),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn verify_reports_invalid_functions() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            define i32 @first() {
+              %a = add i32 %b, 1
+              %b = add i32 1, 1
+              ret i32 %a
+            }
+
+            define void @valid() {
+              ret void
+            }
+
+            define internal void @second(i1 %c) {
+              br i1 %c, label %a, label %a
+            a:
+              %x = add i32 %y, 1
+              %y = add i32 %x, 1
+              ret void
+            }
+        ").unwrap();
+
+        let error = module.verify().unwrap_err();
+        assert_eq!(error.functions, ["first", "second"]);
+        assert!(error.errors.iter().any(|e| e.contains("%b")));
+        assert!(error.errors.iter().any(|e| e.contains("%y")));
+
+        assert!(module.get_named_function("valid").unwrap().verify().is_ok());
+        let second = module.get_named_function("second").unwrap().verify().unwrap_err();
+        assert_eq!(second.functions, ["second"]);
+        assert_eq!(second.errors, error.errors);
+    }
+
+    #[test]
+    fn can_run_pass_pipelines() {
+        let context = Context::new();
+        let mut module = context.parse_ir("
+            define internal i32 @double(i32 %x) {
+              %y = mul i32 %x, 2
+              ret i32 %y
+            }
+
+            define i32 @f(i32 %x) {
+              %y = call i32 @double(i32 %x)
+              ret i32 %y
+            }
+        ").unwrap();
+
+        let mut options = PassBuilderOptions::new();
+        options.set_verify_each(true);
+        options.set_loop_vectorization(true);
+        options.set_slp_vectorization(true);
+        options.set_loop_interleaving(true);
+        module.run_passes("default<O2>", None, &options).unwrap();
+
+        let ir = module.to_string();
+        assert!(!ir.contains("@double"), "{}", ir);
+        assert!(ir.contains("shl i32 %x, 1"), "{}", ir);
+    }
+
+    #[test]
+    fn malformed_pass_pipelines_are_errors() {
+        let context = Context::new();
+        let mut module = context.module_create_with_name("module");
+        let options = PassBuilderOptions::new();
+
+        for pipeline in &["not-a-pass", "function(instcombine", "default<O7>", "instcombine\0"] {
+            match *module.run_passes(pipeline, None, &options).unwrap_err().kind() {
+                ErrorKind::InvalidPassPipeline(ref p, ref message) => {
+                    assert_eq!(p, pipeline);
+                    assert!(!message.is_empty());
+                }
+                ref kind => panic!("unexpected error {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors_have_a_location() {
+        let context = Context::new();
+        let err = context.parse_ir("define i32 @zero() {\n  ret i32 %x\n}\n").unwrap_err();
+        match *err.kind() {
+            ErrorKind::ParseIR(ref diagnostic) => {
+                assert_eq!(diagnostic.line, 2);
+                assert_eq!(diagnostic.column, 11);
+                assert!(diagnostic.message.contains("%x"), "{}", diagnostic.message);
+            }
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn global_ctors_are_appended() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            @llvm.global_ctors = appending global [1 x { i32, void ()*, i8* }] [
+              { i32, void ()*, i8* } { i32 1, void ()* @first, i8* null }
+            ]
+
+            define void @first() {
+              ret void
+            }
+
+            define void @second() {
+              ret void
+            }
+        ").unwrap();
+        module.add_global_ctor(&module.get_named_function("second").unwrap(), 2);
+
+        let ir = module.to_string();
+        assert!(ir.contains("@llvm.global_ctors = appending global [2 x { i32, void ()*, i8* }] \
+                             [{ i32, void ()*, i8* } { i32 1, void ()* @first, i8* null }, \
+                             { i32, void ()*, i8* } { i32 2, void ()* @second, i8* null }]"),
+                "{}", ir);
+        assert!(module.verify().is_ok());
+    }
+
+    #[test]
+    fn legacy_global_ctors_are_converted() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            @llvm.global_ctors = appending global [1 x { i32, void ()* }] [
+              { i32, void ()* } { i32 1, void ()* @first }
+            ]
+
+            define void @first() {
+              ret void
+            }
+
+            define void @second() {
+              ret void
+            }
+        ").unwrap();
+        module.add_global_ctor(&module.get_named_function("second").unwrap(), 2);
+
+        let ir = module.to_string();
+        assert!(ir.contains("@llvm.global_ctors = appending global [2 x { i32, void ()*, i8* }] \
+                             [{ i32, void ()*, i8* } { i32 1, void ()* @first, i8* null }, \
+                             { i32, void ()*, i8* } { i32 2, void ()* @second, i8* null }]"),
+                "{}", ir);
+        assert!(module.verify().is_ok());
+    }
+
+    #[test]
+    fn replaced_global_ctors_are_deleted_when_the_module_is_handed_over() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            @llvm.global_ctors = appending global [0 x { i32, void ()*, i8* }] zeroinitializer
+
+            define void @init() {
+              ret void
+            }
+        ").unwrap();
+        let old = module.get_named_global("llvm.global_ctors").unwrap();
+        module.add_global_ctor(&module.get_named_function("init").unwrap(), 1);
+
+        // The old array can still be used, but is no longer part of the
+        // constructors
+        assert_eq!(old.name(), "");
+        assert!(module.to_string().contains("@0 = private global [0 x { i32, void ()*, i8* }] \
+                                             zeroinitializer"));
+        assert!(module.verify().is_ok());
+
+        let ptr: LLVMModuleRef = module.into();
+        let module: Module = ptr.into();
+        assert!(!module.to_string().contains("@0"));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be functions of the module")]
+    fn global_ctors_must_be_functions_of_the_module() {
+        let context = Context::new();
+        let module = context.module_create_with_name("module");
+        let other = context.module_create_with_name("other");
+        let ty = types::Function::new(context.void_type(), &[], false);
+        module.add_global_ctor(&other.add_function(ty, "init"), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "llvm.global_dtors has entries of type { i64, void ()* }")]
+    fn global_dtors_of_other_types_are_rejected() {
+        let context = Context::new();
+        let module = context.parse_ir("
+            @llvm.global_dtors = appending global [0 x { i64, void ()* }] zeroinitializer
+
+            define void @fini() {
+              ret void
+            }
+        ").unwrap();
+        module.add_global_dtor(&module.get_named_function("fini").unwrap(), 1);
+    }
+}
@@ -0,0 +1,1166 @@
+//! In-process JIT compilation with ORC's `LLJIT`.
+//!
+//! Unlike an `ExecutionEngine`, an [`LLJIT`] takes modules of
+//! [`ThreadSafeContext`]s, organizes symbols into [`JITDylib`]s, and can
+//! compile functions lazily, the first time they are called.
+//!
+//! ```rust
+//! use llvm::*;
+//! use llvm::orc::*;
+//!
+//! initialize_native_target();
+//! initialize_native_asm_printer();
+//!
+//! let context = ThreadSafeContext::new();
+//! let module = context.parse_ir("
+//!     define i64 @add(i64 %x, i64 %y) {
+//!       %z = add i64 %x, %y
+//!       ret i64 %z
+//!     }
+//! ").unwrap();
+//!
+//! let jit = LLJIT::new().unwrap();
+//! jit.add_module(jit.main_dylib(), ThreadSafeModule::new(module, &context)).unwrap();
+//!
+//! let add = jit.lookup::<extern "C" fn(i64, i64) -> i64>("add").unwrap();
+//! assert_eq!(unsafe { add.call(2, 3) }, 5);
+//! ```
+//!
+//! [`LLJIT`]: struct.LLJIT.html
+//! [`ThreadSafeContext`]: struct.ThreadSafeContext.html
+//! [`JITDylib`]: struct.JITDylib.html
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+use std::thread;
+
+use libc::c_void;
+use llvm_sys::prelude::*;
+use llvm_sys::core as llvm;
+use llvm_sys::bit_writer::LLVMWriteBitcodeToMemoryBuffer;
+use llvm_sys::error::*;
+use llvm_sys::target_machine::*;
+use llvm_sys::orc2::*;
+use llvm_sys::orc2::lljit::*;
+use llvm_sys::LLVMLinkage;
+
+use super::*;
+
+error_chain! {
+    links {
+        Module(module::Error, module::ErrorKind);
+    }
+
+    errors {
+        CreateJit(t: String) {
+            description("Error while creating a JIT")
+            display("Error while creating a JIT: '{}'", t)
+        }
+        CreateDylib(name: String, t: String) {
+            description("Error while creating a JITDylib")
+            display("Error while creating JITDylib '{}': '{}'", name, t)
+        }
+        AddModule(t: String) {
+            description("Error while adding a module to a JIT")
+            display("Error while adding a module to a JIT: '{}'", t)
+        }
+        DefineSymbols(t: String) {
+            description("Error while defining symbols in a JITDylib")
+            display("Error while defining symbols in a JITDylib: '{}'", t)
+        }
+        UnknownSymbol(name: String, t: String) {
+            description("Symbol could not be looked up in the JIT")
+            display("Symbol '{}' could not be looked up in the JIT: '{}'", name, t)
+        }
+        SignatureMismatch(name: String, expected: String, found: String) {
+            description("Function has a different signature than requested")
+            display("Function '{}' has type '{}', but '{}' was requested", name, found, expected)
+        }
+        Codegen(t: String) {
+            description("Error while compiling a module")
+            display("Error while compiling a module: '{}'", t)
+        }
+        InvalidName(name: String) {
+            description("Function name is not valid UTF-8")
+            display("Function name '{}' is not valid UTF-8", name)
+        }
+        DisposeJit(t: String) {
+            description("Error while disposing of a JIT")
+            display("Error while disposing of a JIT: '{}'", t)
+        }
+    }
+}
+
+// Converts an error returned by LLVM into its message, consuming it
+unsafe fn take_error(error: LLVMErrorRef) -> ::std::result::Result<(), String> {
+    if error.is_null() {
+        Ok(())
+    } else {
+        let message = LLVMGetErrorMessage(error);
+        let t = CStr::from_ptr(message).to_string_lossy().into_owned();
+        LLVMDisposeErrorMessage(message);
+        Err(t)
+    }
+}
+
+/// A context that can be shared by the modules handed over to a JIT.
+///
+/// It derefs to `ContextRef`, so modules are built in it like in any other
+/// context, before they're wrapped into `ThreadSafeModule`s.
+pub struct ThreadSafeContext {
+    pub ptr: LLVMOrcThreadSafeContextRef,
+}
+impl_llvm_ref!(ThreadSafeContext, LLVMOrcThreadSafeContextRef);
+
+impl ThreadSafeContext {
+    pub fn new() -> Self {
+        unsafe { LLVMOrcCreateNewThreadSafeContext().into() }
+    }
+}
+
+impl Deref for ThreadSafeContext {
+    type Target = ContextRef;
+
+    fn deref(&self) -> &ContextRef {
+        unsafe { LLVMOrcThreadSafeContextGetContext(self.ptr).into() }
+    }
+}
+
+impl fmt::Debug for ThreadSafeContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::orc::ThreadSafeContext({:?})", self.ptr)
+    }
+}
+
+impl Default for ThreadSafeContext {
+    /// Creates a new context
+    fn default() -> Self {
+        ThreadSafeContext::new()
+    }
+}
+
+// The underlying context is reference counted, and lives on as long as
+// modules of it do
+impl Drop for ThreadSafeContext {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMOrcDisposeThreadSafeContext(self.ptr);
+        }
+    }
+}
+
+/// A module together with the `ThreadSafeContext` it was created in, ready
+/// to be added to a JIT.
+pub struct ThreadSafeModule {
+    pub ptr: LLVMOrcThreadSafeModuleRef,
+    // Taken over from the wrapped module
+    verify_before_codegen: bool,
+}
+
+impl ThreadSafeModule {
+    /// Wraps `module`, which keeps `context` alive until it's dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `module` wasn't created in `context`.
+    pub fn new<'ctx>(module: Module<'ctx>, context: &'ctx ThreadSafeContext) -> Self {
+        unsafe {
+            assert!(llvm::LLVMGetModuleContext(module.ptr) == LLVMContextRef::from(&**context),
+                    "module was not created in the given thread safe context");
+            ThreadSafeModule {
+                verify_before_codegen: module.verify_before_codegen,
+                ptr: LLVMOrcCreateNewThreadSafeModule(module.into(), context.ptr),
+            }
+        }
+    }
+
+    // Runs `f` on the module, with its context locked. Panics of `f` are
+    // caught before they reach LLVM, and resumed once it returned.
+    fn with_module<T, F: FnOnce(LLVMModuleRef) -> Result<T>>(&self, f: F) -> Result<T> {
+        type State<F, T> = (Option<F>, Option<thread::Result<Result<T>>>);
+
+        extern "C" fn run<T, F: FnOnce(LLVMModuleRef) -> Result<T>>(ctx: *mut c_void,
+                                                                    module: LLVMModuleRef)
+                                                                    -> LLVMErrorRef {
+            let state = unsafe { &mut *(ctx as *mut State<F, T>) };
+            let f = state.0.take().unwrap();
+            state.1 = Some(panic::catch_unwind(AssertUnwindSafe(|| f(module))));
+            ptr::null_mut()
+        }
+
+        let mut state: State<F, T> = (Some(f), None);
+        unsafe {
+            let error = LLVMOrcThreadSafeModuleWithModuleDo(self.ptr,
+                                                            run::<T, F>,
+                                                            &mut state as *mut _ as *mut c_void);
+            take_error(error).map_err(ErrorKind::AddModule)?;
+        }
+        match state.1 {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => unreachable!("module callback was not run"),
+        }
+    }
+}
+
+impl From<ThreadSafeModule> for LLVMOrcThreadSafeModuleRef {
+    fn from(module: ThreadSafeModule) -> Self {
+        // The caller takes over ownership of the pointer
+        let ptr = module.ptr;
+        ::std::mem::forget(module);
+        ptr
+    }
+}
+
+impl fmt::Debug for ThreadSafeModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::orc::ThreadSafeModule({:?})", self.ptr)
+    }
+}
+
+impl Drop for ThreadSafeModule {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMOrcDisposeThreadSafeModule(self.ptr);
+        }
+    }
+}
+
+/// A JIT "dynamic library": a symbol table that modules and symbols are
+/// added to. It's owned by the `LLJIT` it was created by.
+pub struct JITDylib(LLVMOrcOpaqueJITDylib);
+impl_llvm_type_wrapper!(LLVMOrcJITDylibRef, JITDylib);
+impl_llvm_type_eq!(LLVMOrcJITDylibRef, JITDylib);
+
+impl fmt::Debug for JITDylib {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::orc::JITDylib({:?})", LLVMOrcJITDylibRef::from(self))
+    }
+}
+
+/// A store for the objects compiled by an `LLJIT`, so that identical modules
+/// aren't compiled again, e.g. on the next run of a program.
+///
+/// Objects are keyed by a hash of the bitcode of their module and of the
+/// settings they were compiled with, e.g. the target triple, CPU and
+/// optimization level of the JIT.
+pub trait ObjectCache {
+    /// Returns the object stored for `key`, if there is one.
+    fn load(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Stores the `object` compiled for `key`.
+    fn store(&self, key: &str, object: &[u8]);
+}
+
+/// An `ObjectCache` storing objects as files in a directory.
+///
+/// Failing to store an object only means that it's compiled again the next
+/// time, so I/O errors are ignored.
+#[derive(Debug, Clone)]
+pub struct FileObjectCache {
+    dir: PathBuf,
+}
+
+impl FileObjectCache {
+    /// Creates a cache in `dir`, which is created when the first object is
+    /// stored.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FileObjectCache {
+            dir: dir.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.o", key))
+    }
+}
+
+impl ObjectCache for FileObjectCache {
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(key)).ok()
+    }
+
+    fn store(&self, key: &str, object: &[u8]) {
+        // Objects are written to a temporary file first, so that concurrent
+        // runs never load a partially written object
+        let path = self.path(key);
+        let temporary = self.dir.join(format!("{}.o.{}.tmp", key, ::std::process::id()));
+        let stored = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, object))
+            .and_then(|_| fs::rename(&temporary, &path));
+        if stored.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+/// An in-process JIT compiler.
+///
+/// Symbols are always looked up in the main dylib; symbols of other dylibs
+/// can be made available there with `add_lazy_reexports`.
+///
+/// The native target and asm printer need to be initialized before creating
+/// a JIT.
+///
+/// Errors that happen while code runs rather than in a call to the JIT are
+/// collected and returned by `take_errors`. When compiling a lazily added
+/// function fails, there's no result to return from the call, so the
+/// process is aborted with the error instead.
+pub struct LLJIT {
+    pub ptr: LLVMOrcLLJITRef,
+    // The types of the functions added to the JIT, by dylib and name, to
+    // check the signatures of lookups. They're created in a context of the
+    // JIT, since contexts can't be used by several threads at once.
+    signatures: RefCell<HashMap<(LLVMOrcJITDylibRef, String), String>>,
+    signature_context: Context,
+    // Filled by the error reporter of the execution session, which may run
+    // on any thread that calls JIT compiled code
+    errors: Box<Mutex<Vec<String>>>,
+    // Created the first time a function is added lazily
+    call_through_manager: Cell<LLVMOrcLazyCallThroughManagerRef>,
+    stubs_manager: Cell<LLVMOrcIndirectStubsManagerRef>,
+    object_cache: RefCell<Option<Box<dyn ObjectCache>>>,
+    target: TargetSettings,
+}
+
+// The settings of the target machines of a JIT. The JIT is created with a
+// `JITTargetMachineBuilder` for them, so that the objects compiled for its
+// cache are the ones it would compile itself.
+struct TargetSettings {
+    triple: CString,
+    cpu: CString,
+    features: CString,
+    opt_level: LLVMCodeGenOptLevel,
+    reloc_mode: LLVMRelocMode,
+    code_model: LLVMCodeModel,
+}
+
+impl TargetSettings {
+    // Returns the settings for the host, with position independent code
+    // that can be loaded anywhere in memory
+    unsafe fn host() -> ::std::result::Result<TargetSettings, String> {
+        let mut builder = ptr::null_mut();
+        take_error(LLVMOrcJITTargetMachineBuilderDetectHost(&mut builder))?;
+        let triple = take_message(LLVMOrcJITTargetMachineBuilderGetTargetTriple(builder));
+        LLVMOrcDisposeJITTargetMachineBuilder(builder);
+        Ok(TargetSettings {
+            triple: CString::new(triple).unwrap(),
+            cpu: CString::new(take_message(LLVMGetHostCPUName())).unwrap(),
+            features: CString::new(take_message(LLVMGetHostCPUFeatures())).unwrap(),
+            opt_level: LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            reloc_mode: LLVMRelocMode::LLVMRelocPIC,
+            code_model: LLVMCodeModel::LLVMCodeModelJITDefault,
+        })
+    }
+
+    unsafe fn create_target_machine(&self) -> ::std::result::Result<LLVMTargetMachineRef, String> {
+        let mut target = ptr::null_mut();
+        let mut message = ptr::null_mut();
+        if LLVMGetTargetFromTriple(self.triple.as_ptr(), &mut target, &mut message) != 0 {
+            return Err(take_message(message));
+        }
+        Ok(LLVMCreateTargetMachine(target,
+                                   self.triple.as_ptr(),
+                                   self.cpu.as_ptr(),
+                                   self.features.as_ptr(),
+                                   self.opt_level,
+                                   self.reloc_mode,
+                                   self.code_model))
+    }
+
+    // Returns the key of the object compiled from `bitcode` with these
+    // settings
+    fn object_key(&self, bitcode: &[u8]) -> String {
+        let settings = format!("{:?} {:?} {:?}", self.opt_level, self.reloc_mode, self.code_model);
+        object_key(&[bitcode,
+                     self.triple.as_bytes(),
+                     self.cpu.as_bytes(),
+                     self.features.as_bytes(),
+                     settings.as_bytes()])
+    }
+}
+
+thread_local! {
+    // The errors reported to `report_error` on this thread, until they're
+    // taken by `LLJIT::take_errors`
+    static THREAD_ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// Called by lazy stubs in place of the function they stand for when
+// compiling it fails, right after the error was reported to `report_error`
+// on the same thread. There's no result to return to the caller, so the
+// process is aborted.
+extern "C" fn lazy_compilation_failed() -> ! {
+    let errors = THREAD_ERRORS.with(|e| e.borrow_mut().split_off(0));
+    eprintln!("fatal error: compiling a lazily added function failed: {}",
+              errors.join("; "));
+    ::std::process::abort()
+}
+
+// The error reporter of the execution session of a JIT, `errors` being its
+// `LLJIT::errors`
+extern "C" fn report_error(errors: *mut c_void, error: LLVMErrorRef) {
+    unsafe {
+        if let Err(t) = take_error(error) {
+            THREAD_ERRORS.with(|e| e.borrow_mut().push(t.clone()));
+            let errors = &*(errors as *const Mutex<Vec<String>>);
+            // A poisoned lock still holds the errors
+            let mut errors = errors.lock().unwrap_or_else(|e| e.into_inner());
+            errors.push(t);
+        }
+    }
+}
+
+impl LLJIT {
+    /// Creates a JIT for the host.
+    pub fn new() -> Result<LLJIT> {
+        unsafe {
+            let target = TargetSettings::host().map_err(ErrorKind::CreateJit)?;
+            let machine = target.create_target_machine().map_err(ErrorKind::CreateJit)?;
+            // Each of these calls takes ownership of its last argument
+            let builder = LLVMOrcCreateLLJITBuilder();
+            LLVMOrcLLJITBuilderSetJITTargetMachineBuilder(
+                builder,
+                LLVMOrcJITTargetMachineBuilderCreateFromTargetMachine(machine));
+            let mut ptr = ptr::null_mut();
+            if let Err(t) = take_error(LLVMOrcCreateLLJIT(&mut ptr, builder)) {
+                bail!(ErrorKind::CreateJit(t));
+            }
+            let errors = Box::new(Mutex::new(Vec::new()));
+            LLVMOrcExecutionSessionSetErrorReporter(LLVMOrcLLJITGetExecutionSession(ptr),
+                                                    report_error,
+                                                    &*errors as *const _ as *mut c_void);
+            Ok(LLJIT {
+                ptr: ptr,
+                signatures: RefCell::new(HashMap::new()),
+                signature_context: Context::new(),
+                errors: errors,
+                call_through_manager: Cell::new(ptr::null_mut()),
+                stubs_manager: Cell::new(ptr::null_mut()),
+                object_cache: RefCell::new(None),
+                target: target,
+            })
+        }
+    }
+
+    /// Returns the dylib that symbols are looked up in.
+    pub fn main_dylib(&self) -> &JITDylib {
+        unsafe { LLVMOrcLLJITGetMainJITDylib(self.ptr).into() }
+    }
+
+    /// Creates a new, empty dylib named `name`.
+    pub fn create_dylib(&self, name: &str) -> Result<&JITDylib> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let session = LLVMOrcLLJITGetExecutionSession(self.ptr);
+            let mut dylib = ptr::null_mut();
+            let error = LLVMOrcExecutionSessionCreateJITDylib(session, &mut dylib, c_name.as_ptr());
+            if let Err(t) = take_error(error) {
+                bail!(ErrorKind::CreateDylib(name.into(), t));
+            }
+            Ok(dylib.into())
+        }
+    }
+
+    /// Returns the dylib named `name`, if there is one.
+    pub fn get_dylib(&self, name: &str) -> Option<&JITDylib> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let session = LLVMOrcLLJITGetExecutionSession(self.ptr);
+            let dylib = LLVMOrcExecutionSessionGetJITDylibByName(session, c_name.as_ptr());
+            if dylib.is_null() {
+                None
+            } else {
+                Some(dylib.into())
+            }
+        }
+    }
+
+    /// Makes `add_module` look up the objects of modules in `cache`, and
+    /// store them there after compiling them.
+    ///
+    /// Modules are then compiled when they're added, unless their object is
+    /// found in the cache, rather than when their symbols are first looked
+    /// up. Modules added with `add_lazy_module` are not cached.
+    pub fn set_object_cache<C: ObjectCache + 'static>(&self, cache: C) {
+        *self.object_cache.borrow_mut() = Some(Box::new(cache));
+    }
+
+    /// Adds `module` to `dylib`. It's compiled as a whole the first time one
+    /// of its symbols is looked up, or right away if an object cache is set.
+    ///
+    /// The module is verified first if that was enabled with
+    /// `Module::set_verify_before_codegen`.
+    pub fn add_module(&self, dylib: &JITDylib, module: ThreadSafeModule) -> Result<()> {
+        self.prepare_module(dylib, &module, false)?;
+        let cache = self.object_cache.borrow();
+        unsafe {
+            let error = match *cache {
+                Some(ref cache) => {
+                    let object = self.cached_object(&module, &**cache)?;
+                    let buffer = MemoryBuffer::from_slice_copy(&object, "cached object");
+                    LLVMOrcLLJITAddObjectFile(self.ptr, dylib.into(), buffer.into())
+                }
+                None => LLVMOrcLLJITAddLLVMIRModule(self.ptr, dylib.into(), module.into()),
+            };
+            take_error(error).map_err(|t| ErrorKind::AddModule(t).into())
+        }
+    }
+
+    /// Adds `module` to `dylib`, compiling it the first time one of its
+    /// functions is called rather than looked up.
+    ///
+    /// The functions of the module are looked up as stubs, which compile the
+    /// module on their first call. Modules are compiled as a whole, so add
+    /// each function in a module of its own to compile them individually.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    /// use llvm::orc::*;
+    ///
+    /// initialize_native_target();
+    /// initialize_native_asm_printer();
+    ///
+    /// let context = ThreadSafeContext::new();
+    /// let jit = LLJIT::new().unwrap();
+    /// for ir in &["define i32 @one() {\n  ret i32 1\n}",
+    ///             "define i32 @two() {\n  ret i32 2\n}"] {
+    ///     let module = context.parse_ir(ir).unwrap();
+    ///     jit.add_lazy_module(jit.main_dylib(), ThreadSafeModule::new(module, &context))
+    ///         .unwrap();
+    /// }
+    ///
+    /// // Only compiles `one`
+    /// let one = jit.lookup::<extern "C" fn() -> i32>("one").unwrap();
+    /// assert_eq!(unsafe { one.call() }, 1);
+    /// ```
+    pub fn add_lazy_module(&self, dylib: &JITDylib, module: ThreadSafeModule) -> Result<()> {
+        let names = self.prepare_module(dylib, &module, true)?;
+        unsafe {
+            let error = LLVMOrcLLJITAddLLVMIRModule(self.ptr, dylib.into(), module.into());
+            take_error(error).map_err(ErrorKind::AddModule)?;
+        }
+        let aliases = names.iter()
+            .map(|name| (name.as_str(), format!("{}.impl", name)))
+            .collect::<Vec<_>>();
+        self.define_lazy_reexports(dylib, dylib, &aliases)
+    }
+
+    /// Defines stubs in `target` for the functions `names` of `source`,
+    /// which compile them the first time they're called.
+    ///
+    /// This is how symbols of other dylibs are made available for lookups
+    /// in the main dylib.
+    pub fn add_lazy_reexports(&self,
+                              target: &JITDylib,
+                              source: &JITDylib,
+                              names: &[&str]) -> Result<()> {
+        let aliases = names.iter()
+            .map(|&name| (name, name.to_string()))
+            .collect::<Vec<_>>();
+        self.define_lazy_reexports(target, source, &aliases)?;
+
+        let mut signatures = self.signatures.borrow_mut();
+        for &name in names {
+            let key = (source.into(), name.to_string());
+            if let Some(signature) = signatures.get(&key).cloned() {
+                signatures.insert((target.into(), name.into()), signature);
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines `name` in `dylib` as the host function `function`, so that
+    /// JIT compiled code can call it.
+    ///
+    /// ```rust
+    /// use llvm::*;
+    /// use llvm::orc::*;
+    ///
+    /// extern "C" fn square(x: i64) -> i64 {
+    ///     x * x
+    /// }
+    ///
+    /// initialize_native_target();
+    /// initialize_native_asm_printer();
+    ///
+    /// let jit = LLJIT::new().unwrap();
+    /// jit.define_function(jit.main_dylib(), "square", square as extern "C" fn(i64) -> i64)
+    ///     .unwrap();
+    ///
+    /// let square = jit.lookup::<extern "C" fn(i64) -> i64>("square").unwrap();
+    /// assert_eq!(unsafe { square.call(3) }, 9);
+    /// ```
+    pub fn define_function<F: JitSignature>(&self,
+                                            dylib: &JITDylib,
+                                            name: &str,
+                                            function: F) -> Result<()> {
+        let flags = LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported as u8 |
+            LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsCallable as u8;
+        self.define_absolute(dylib, name, function.as_ptr() as u64, flags)?;
+        self.signatures.borrow_mut().insert((dylib.into(), name.into()), self.signature::<F>());
+        Ok(())
+    }
+
+    /// Defines `name` in `dylib` as the host data at `address`.
+    pub fn define_data(&self, dylib: &JITDylib, name: &str, address: *const ()) -> Result<()> {
+        let flags = LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported as u8;
+        self.define_absolute(dylib, name, address as u64, flags)
+    }
+
+    /// Makes the symbols of the host process, e.g. those of the C library,
+    /// available to the code in `dylib`.
+    pub fn add_process_symbols(&self, dylib: &JITDylib) -> Result<()> {
+        unsafe {
+            let mut generator = ptr::null_mut();
+            let error = LLVMOrcCreateDynamicLibrarySearchGeneratorForProcess(
+                &mut generator,
+                LLVMOrcLLJITGetGlobalPrefix(self.ptr),
+                None,
+                ptr::null_mut());
+            take_error(error).map_err(ErrorKind::DefineSymbols)?;
+            LLVMOrcJITDylibAddGenerator(dylib.into(), generator);
+        }
+        Ok(())
+    }
+
+    /// Looks up the address of the symbol `name` in the main dylib,
+    /// compiling it if needed.
+    pub fn lookup_address(&self, name: &str) -> Result<u64> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let mut address = 0;
+            let error = LLVMOrcLLJITLookup(self.ptr, &mut address, c_name.as_ptr());
+            if let Err(t) = take_error(error) {
+                bail!(ErrorKind::UnknownSymbol(name.into(), t));
+            }
+            Ok(address)
+        }
+    }
+
+    /// Looks up the function `name` in the main dylib, compiling it if
+    /// needed.
+    ///
+    /// The signature `F` is checked against the LLVM type of functions added
+    /// through modules or `define_function`; the signature of other symbols
+    /// is unknown and not checked.
+    pub fn lookup<'jit, F: JitSignature>(&'jit self, name: &str) -> Result<JitFunction<'jit, F>> {
+        let key = (self.main_dylib().into(), name.to_string());
+        if let Some(found) = self.signatures.borrow().get(&key) {
+            let expected = self.signature::<F>();
+            if *found != expected {
+                bail!(ErrorKind::SignatureMismatch(name.into(), expected, found.clone()));
+            }
+        }
+        let address = self.lookup_address(name)?;
+        unsafe { Ok(JitFunction::from_address(address)) }
+    }
+
+    /// Returns the errors that happened since the last call, outside of the
+    /// calls that return them.
+    pub fn take_errors(&self) -> Vec<String> {
+        THREAD_ERRORS.with(|e| e.borrow_mut().clear());
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        ::std::mem::take(&mut *errors)
+    }
+
+    /// Disposes of the JIT, returning the errors that happen while doing
+    /// so, which are ignored when it's dropped.
+    pub fn dispose(mut self) -> Result<()> {
+        let ptr = ::std::mem::replace(&mut self.ptr, ptr::null_mut());
+        unsafe {
+            take_error(LLVMOrcDisposeLLJIT(ptr)).map_err(|t| ErrorKind::DisposeJit(t).into())
+        }
+    }
+
+    /// Returns the target triple of the code compiled by the JIT.
+    pub fn triple(&self) -> &str {
+        unsafe { c_str_to_str!(LLVMOrcLLJITGetTripleString(self.ptr)) }
+    }
+
+    // Verifies `module` if needed and records the types of its functions as
+    // those of `dylib`. With `rename`, the externally visible functions it
+    // defines are renamed to `<name>.impl` so that stubs can take their
+    // names, and their original names are returned.
+    fn prepare_module(&self,
+                      dylib: &JITDylib,
+                      module: &ThreadSafeModule,
+                      rename: bool) -> Result<Vec<String>> {
+        let verify = module.verify_before_codegen;
+        module.with_module(|module| unsafe {
+            let mut wrapper: Module = module.into();
+            wrapper.set_verify_before_codegen(verify);
+            let verified = wrapper.verify_before_codegen();
+            let _: LLVMModuleRef = wrapper.into();
+            verified?;
+
+            let mut signatures = self.signatures.borrow_mut();
+            let mut renamed = Vec::new();
+            for function in module::functions(module) {
+                if llvm::LLVMIsDeclaration(function) != 0 {
+                    continue;
+                }
+                let mut len = 0;
+                let name = llvm::LLVMGetValueName2(function, &mut len);
+                let name = ::std::slice::from_raw_parts(name as *const u8, len);
+                let name = match ::std::str::from_utf8(name) {
+                    Ok(name) => name.to_string(),
+                    Err(_) => {
+                        bail!(ErrorKind::InvalidName(String::from_utf8_lossy(name).into_owned()))
+                    }
+                };
+                let ty: &Type = llvm::LLVMGlobalGetValueType(function).into();
+                signatures.insert((dylib.into(), name.clone()), ty.to_string());
+
+                if rename && llvm::LLVMGetLinkage(function) == LLVMLinkage::LLVMExternalLinkage {
+                    let c_name = CString::new(format!("{}.impl", name)).unwrap();
+                    llvm::LLVMSetValueName2(function, c_name.as_ptr(), c_name.as_bytes().len());
+                    renamed.push(name);
+                }
+            }
+            Ok(renamed)
+        })
+    }
+
+    // Returns the object of `module` from `cache`, compiling and storing it
+    // if it's not there yet
+    fn cached_object(&self, module: &ThreadSafeModule, cache: &dyn ObjectCache) -> Result<Vec<u8>> {
+        module.with_module(|module| unsafe {
+            // Modules without a data layout get the JIT's, as they would when
+            // the JIT compiles them
+            if CStr::from_ptr(llvm::LLVMGetDataLayoutStr(module)).to_bytes().is_empty() {
+                llvm::LLVMSetDataLayout(module, LLVMOrcLLJITGetDataLayoutStr(self.ptr));
+            }
+
+            let bitcode: MemoryBuffer = LLVMWriteBitcodeToMemoryBuffer(module).into();
+            let key = self.target.object_key(bitcode.as_bytes());
+
+            if let Some(object) = cache.load(&key) {
+                return Ok(object);
+            }
+            let object = compile(module, &self.target).map_err(ErrorKind::Codegen)?;
+            cache.store(&key, &object);
+            Ok(object)
+        })
+    }
+
+    fn define_absolute(&self, dylib: &JITDylib, name: &str, address: u64, flags: u8) -> Result<()> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let mut symbol = LLVMJITCSymbolMapPair {
+                Name: LLVMOrcLLJITMangleAndIntern(self.ptr, c_name.as_ptr()),
+                Sym: LLVMJITEvaluatedSymbol {
+                    Address: address,
+                    Flags: LLVMJITSymbolFlags {
+                        GenericFlags: flags,
+                        TargetFlags: 0,
+                    },
+                },
+            };
+            let unit = LLVMOrcAbsoluteSymbols(&mut symbol, 1);
+            self.define(dylib, unit)
+        }
+    }
+
+    // Defines stubs named after the first element of each pair of `aliases`
+    // in `target`, which compile the function named after the second one in
+    // `source`
+    fn define_lazy_reexports(&self,
+                             target: &JITDylib,
+                             source: &JITDylib,
+                             aliases: &[(&str, String)]) -> Result<()> {
+        if aliases.is_empty() {
+            return Ok(());
+        }
+        let flags = LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported as u8 |
+            LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsCallable as u8;
+        unsafe {
+            self.create_lazy_managers()?;
+            let mut pairs = aliases.iter().map(|&(alias, ref aliasee)| {
+                let c_alias = CString::new(alias).unwrap();
+                let c_aliasee = CString::new(aliasee.as_str()).unwrap();
+                LLVMOrcCSymbolAliasMapPair {
+                    Name: LLVMOrcLLJITMangleAndIntern(self.ptr, c_alias.as_ptr()),
+                    Entry: LLVMOrcCSymbolAliasMapEntry {
+                        Name: LLVMOrcLLJITMangleAndIntern(self.ptr, c_aliasee.as_ptr()),
+                        Flags: LLVMJITSymbolFlags {
+                            GenericFlags: flags,
+                            TargetFlags: 0,
+                        },
+                    },
+                }
+            }).collect::<Vec<_>>();
+            let unit = LLVMOrcLazyReexports(self.call_through_manager.get(),
+                                            self.stubs_manager.get(),
+                                            source.into(),
+                                            pairs.as_mut_ptr(),
+                                            pairs.len());
+            self.define(target, unit)
+        }
+    }
+
+    // Returns the LLVM type of functions with signature `F`, as a string
+    // since types of different contexts can't be compared directly
+    fn signature<F: JitSignature>(&self) -> String {
+        F::get_type_in_context(&self.signature_context).to_string()
+    }
+
+    unsafe fn create_lazy_managers(&self) -> Result<()> {
+        if !self.call_through_manager.get().is_null() {
+            return Ok(());
+        }
+        let triple = LLVMOrcLLJITGetTripleString(self.ptr);
+        let mut manager = ptr::null_mut();
+        let error = LLVMOrcCreateLocalLazyCallThroughManager(
+            triple,
+            LLVMOrcLLJITGetExecutionSession(self.ptr),
+            lazy_compilation_failed as *const () as u64,
+            &mut manager);
+        take_error(error).map_err(ErrorKind::CreateJit)?;
+        self.call_through_manager.set(manager);
+        self.stubs_manager.set(LLVMOrcCreateLocalIndirectStubsManager(triple));
+        Ok(())
+    }
+
+    // Defines the symbols of `unit` in `dylib`, taking ownership of it
+    unsafe fn define(&self, dylib: &JITDylib, unit: LLVMOrcMaterializationUnitRef) -> Result<()> {
+        let error = LLVMOrcJITDylibDefine(dylib.into(), unit);
+        take_error(error).map_err(|t| {
+            // Ownership of the unit is only transferred on success
+            LLVMOrcDisposeMaterializationUnit(unit);
+            ErrorKind::DefineSymbols(t).into()
+        })
+    }
+}
+
+// Compiles `module` into an object file with the settings of a JIT
+unsafe fn compile(module: LLVMModuleRef,
+                  target: &TargetSettings) -> ::std::result::Result<Vec<u8>, String> {
+    let machine = target.create_target_machine()?;
+    let mut message = ptr::null_mut();
+    let mut buffer = ptr::null_mut();
+    let failed = LLVMTargetMachineEmitToMemoryBuffer(machine,
+                                                     module,
+                                                     LLVMCodeGenFileType::LLVMObjectFile,
+                                                     &mut message,
+                                                     &mut buffer) != 0;
+    LLVMDisposeTargetMachine(machine);
+    if failed {
+        Err(take_message(message))
+    } else {
+        let buffer: MemoryBuffer = buffer.into();
+        Ok(buffer.as_bytes().to_vec())
+    }
+}
+
+// Hashes `parts` into a cache key with 64-bit FNV-1a, which unlike the
+// hashers of the standard library is stable across Rust versions
+fn object_key(parts: &[&[u8]]) -> String {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for part in parts {
+        let len = (part.len() as u64).to_le_bytes();
+        for &byte in len.iter().chain(part.iter()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+
+impl fmt::Debug for LLJIT {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "llvm::orc::LLJIT({:?})", self.ptr)
+    }
+}
+
+impl Drop for LLJIT {
+    fn drop(&mut self) {
+        unsafe {
+            // Stubs may still refer to the managers, so they go last.
+            // Errors are only returned by `dispose`.
+            if !self.ptr.is_null() {
+                let _ = take_error(LLVMOrcDisposeLLJIT(self.ptr));
+            }
+            if !self.stubs_manager.get().is_null() {
+                LLVMOrcDisposeIndirectStubsManager(self.stubs_manager.get());
+                LLVMOrcDisposeLazyCallThroughManager(self.call_through_manager.get());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jit() -> LLJIT {
+        initialize_native_target();
+        initialize_native_asm_printer();
+        LLJIT::new().unwrap()
+    }
+
+    fn module(context: &ThreadSafeContext, ir: &str) -> ThreadSafeModule {
+        ThreadSafeModule::new(context.parse_ir(ir).unwrap(), context)
+    }
+
+    #[test]
+    fn lookups_check_signatures() {
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        jit.add_module(jit.main_dylib(), module(&context, "
+            define i64 @double(i64 %x) {
+              %y = mul i64 %x, 2
+              ret i64 %y
+            }
+        ")).unwrap();
+
+        let double = jit.lookup::<extern "C" fn(i64) -> i64>("double").unwrap();
+        assert_eq!(unsafe { double.call(21) }, 42);
+
+        match *jit.lookup::<extern "C" fn(i32) -> i64>("double").unwrap_err().kind() {
+            ErrorKind::SignatureMismatch(ref name, ..) => assert_eq!(name, "double"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+        match *jit.lookup::<extern "C" fn()>("missing").unwrap_err().kind() {
+            ErrorKind::UnknownSymbol(ref name, _) => assert_eq!(name, "missing"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn jit_code_can_call_host_functions_and_data() {
+        extern "C" fn add_one(x: i64) -> i64 {
+            x + 1
+        }
+        static OFFSET: i64 = 100;
+
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        jit.define_function(jit.main_dylib(), "add_one", add_one as extern "C" fn(i64) -> i64)
+            .unwrap();
+        jit.define_data(jit.main_dylib(), "offset", &OFFSET as *const i64 as *const ())
+            .unwrap();
+        jit.add_module(jit.main_dylib(), module(&context, "
+            @offset = external global i64
+            declare i64 @add_one(i64)
+
+            define i64 @f(i64 %x) {
+              %y = call i64 @add_one(i64 %x)
+              %offset = load i64, i64* @offset
+              %z = add i64 %y, %offset
+              ret i64 %z
+            }
+        ")).unwrap();
+
+        let f = jit.lookup::<extern "C" fn(i64) -> i64>("f").unwrap();
+        assert_eq!(unsafe { f.call(1) }, 102);
+    }
+
+    #[test]
+    fn lazy_functions_are_only_compiled_when_called() {
+        extern "C" fn answer() -> i32 {
+            42
+        }
+
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        jit.add_lazy_module(jit.main_dylib(), module(&context, "
+            declare i32 @answer()
+
+            define i32 @call_answer() {
+              %x = call i32 @answer()
+              ret i32 %x
+            }
+        ")).unwrap();
+
+        // Compiling `call_answer` before `answer` is defined would fail
+        let call_answer = jit.lookup::<extern "C" fn() -> i32>("call_answer").unwrap();
+        assert!(jit.lookup::<extern "C" fn() -> i64>("call_answer").is_err());
+        jit.define_function(jit.main_dylib(), "answer", answer as extern "C" fn() -> i32)
+            .unwrap();
+        assert_eq!(unsafe { call_answer.call() }, 42);
+        assert!(jit.take_errors().is_empty());
+        jit.dispose().unwrap();
+    }
+
+    #[test]
+    fn failed_lazy_compilation_aborts() {
+        // The call aborts, so it's made in a child process running this test
+        if ::std::env::var_os("LLVM_CALL_BROKEN_LAZY_FUNCTION").is_some() {
+            let context = ThreadSafeContext::new();
+            let jit = jit();
+            jit.add_lazy_module(jit.main_dylib(), module(&context, "
+                declare void @missing()
+
+                define void @broken() {
+                  call void @missing()
+                  ret void
+                }
+            ")).unwrap();
+
+            let broken = jit.lookup::<extern "C" fn()>("broken").unwrap();
+            unsafe { broken.call() };
+            return;
+        }
+
+        let output = ::std::process::Command::new(::std::env::current_exe().unwrap())
+            .args(["--exact", "orc::tests::failed_lazy_compilation_aborts", "--nocapture"])
+            .env("LLVM_CALL_BROKEN_LAZY_FUNCTION", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("compiling a lazily added function failed"), "{}", stderr);
+        assert!(stderr.contains("missing"), "{}", stderr);
+    }
+
+    #[test]
+    fn signatures_are_checked_per_dylib() {
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        let library = jit.create_dylib("library").unwrap();
+        jit.add_module(library, module(&context, "
+            define i64 @f(i64 %x) {
+              ret i64 %x
+            }
+        ")).unwrap();
+        jit.add_module(jit.main_dylib(), module(&context, "
+            define i32 @f() {
+              ret i32 1
+            }
+        ")).unwrap();
+
+        assert!(jit.lookup::<extern "C" fn(i64) -> i64>("f").is_err());
+        let f = jit.lookup::<extern "C" fn() -> i32>("f").unwrap();
+        assert_eq!(unsafe { f.call() }, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "cache is broken")]
+    fn panics_of_object_caches_are_propagated() {
+        struct BrokenCache;
+
+        impl ObjectCache for BrokenCache {
+            fn load(&self, _: &str) -> Option<Vec<u8>> {
+                panic!("cache is broken")
+            }
+
+            fn store(&self, _: &str, _: &[u8]) {}
+        }
+
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        jit.set_object_cache(BrokenCache);
+        let _ = jit.add_module(jit.main_dylib(), module(&context, "
+            define void @f() {
+              ret void
+            }
+        "));
+    }
+
+    #[test]
+    fn symbols_of_other_dylibs_can_be_reexported() {
+        let context = ThreadSafeContext::new();
+        let jit = jit();
+        let library = jit.create_dylib("library").unwrap();
+        assert_eq!(jit.get_dylib("library"), Some(library));
+        assert!(jit.get_dylib("other").is_none());
+
+        jit.add_module(library, module(&context, "
+            define i32 @answer() {
+              ret i32 42
+            }
+        ")).unwrap();
+        assert!(jit.lookup_address("answer").is_err());
+
+        jit.add_lazy_reexports(jit.main_dylib(), library, &["answer"]).unwrap();
+        let answer = jit.lookup::<extern "C" fn() -> i32>("answer").unwrap();
+        assert_eq!(unsafe { answer.call() }, 42);
+    }
+
+    #[test]
+    fn cached_objects_skip_codegen() {
+        use std::rc::Rc;
+
+        // Counts the objects loaded from and stored to a file cache. Objects
+        // are only stored after compiling them.
+        struct CountingCache {
+            inner: FileObjectCache,
+            loads: Rc<Cell<usize>>,
+            stores: Rc<Cell<usize>>,
+        }
+
+        impl ObjectCache for CountingCache {
+            fn load(&self, key: &str) -> Option<Vec<u8>> {
+                let object = self.inner.load(key);
+                if object.is_some() {
+                    self.loads.set(self.loads.get() + 1);
+                }
+                object
+            }
+
+            fn store(&self, key: &str, object: &[u8]) {
+                self.stores.set(self.stores.get() + 1);
+                self.inner.store(key, object);
+            }
+        }
+
+        let dir = ::std::env::temp_dir()
+            .join(format!("llvm-object-cache-{}", ::std::process::id()));
+        let loads = Rc::new(Cell::new(0));
+        let stores = Rc::new(Cell::new(0));
+        for &(x, stored, loaded) in &[(3, 1, 0), (4, 1, 1)] {
+            let context = ThreadSafeContext::new();
+            let jit = jit();
+            jit.set_object_cache(CountingCache {
+                inner: FileObjectCache::new(&dir),
+                loads: loads.clone(),
+                stores: stores.clone(),
+            });
+            jit.add_module(jit.main_dylib(), module(&context, "
+                define i64 @cube(i64 %x) {
+                  %square = mul i64 %x, %x
+                  %cube = mul i64 %square, %x
+                  ret i64 %cube
+                }
+            ")).unwrap();
+
+            let cube = jit.lookup::<extern "C" fn(i64) -> i64>("cube").unwrap();
+            assert_eq!(unsafe { cube.call(x) }, x * x * x);
+            assert_eq!((stores.get(), loads.get()), (stored, loaded));
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn object_keys_depend_on_target_settings() {
+        let host = unsafe { TargetSettings::host() }.unwrap();
+        let mut other = unsafe { TargetSettings::host() }.unwrap();
+        assert_eq!(host.object_key(b"bitcode"), other.object_key(b"bitcode"));
+        assert!(host.object_key(b"bitcode") != host.object_key(b"other"));
+
+        other.opt_level = LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive;
+        assert!(host.object_key(b"bitcode") != other.object_key(b"bitcode"));
+        other = unsafe { TargetSettings::host() }.unwrap();
+        other.cpu = CString::new("generic").unwrap();
+        assert!(host.object_key(b"bitcode") != other.object_key(b"bitcode"));
+    }
+
+    #[test]
+    #[should_panic(expected = "thread safe context")]
+    fn modules_must_belong_to_the_thread_safe_context() {
+        let context = ThreadSafeContext::new();
+        let other = Context::new();
+        let module = other.module_create_with_name("module");
+        ThreadSafeModule::new(module, &context);
+    }
+}
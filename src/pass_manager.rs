@@ -1,6 +1,132 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+
 use llvm_sys::core as llvm;
 use llvm_sys::prelude::*;
+use llvm_sys::transforms::instcombine::LLVMAddInstructionCombiningPass;
+use llvm_sys::transforms::ipo::*;
+use llvm_sys::transforms::pass_builder::*;
+use llvm_sys::transforms::pass_manager_builder::*;
+use llvm_sys::transforms::scalar::*;
+use llvm_sys::transforms::util::*;
+use llvm_sys::transforms::vectorize::*;
+
+use function::Function;
+use module::Module;
+
+// Generates methods adding a transform pass to a pass manager
+macro_rules! add_passes {
+    ($($(#[$attr: meta])* fn $name: ident => $fn: path;)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $name(&mut self) {
+                unsafe {
+                    $fn(self.ptr)
+                }
+            }
+        )*
+    }
+}
+
+// Generates the methods adding the passes that work on single functions,
+// which can be added to module and function pass managers alike
+macro_rules! add_function_passes {
+    () => {
+        add_passes! {
+            /// Combines redundant instructions (`instcombine`)
+            fn add_instruction_combining_pass => LLVMAddInstructionCombiningPass;
+            /// Removes redundant instructions (`instsimplify`)
+            fn add_instruction_simplify_pass => LLVMAddInstructionSimplifyPass;
+            /// Promotes allocas to SSA registers (`mem2reg`)
+            fn add_promote_memory_to_register_pass => LLVMAddPromoteMemoryToRegisterPass;
+            /// Demotes SSA registers to allocas (`reg2mem`)
+            fn add_demote_memory_to_register_pass => LLVMAddDemoteMemoryToRegisterPass;
+            /// Breaks up aggregate allocas (`sroa`)
+            fn add_scalar_repl_aggregates_pass => LLVMAddScalarReplAggregatesPass;
+            /// Eliminates redundant instructions and loads (`gvn`)
+            fn add_gvn_pass => LLVMAddGVNPass;
+            /// Eliminates redundant instructions and loads (`newgvn`)
+            fn add_new_gvn_pass => LLVMAddNewGVNPass;
+            /// Eliminates trivially redundant instructions (`early-cse`)
+            fn add_early_cse_pass => LLVMAddEarlyCSEPass;
+            /// Eliminates dead instructions (`dce`)
+            fn add_dce_pass => LLVMAddDCEPass;
+            /// Eliminates dead instructions, assuming they're dead until
+            /// proven otherwise (`adce`)
+            fn add_aggressive_dce_pass => LLVMAddAggressiveDCEPass;
+            /// Eliminates dead bits of instructions (`bdce`)
+            fn add_bit_tracking_dce_pass => LLVMAddBitTrackingDCEPass;
+            /// Eliminates dead stores (`dse`)
+            fn add_dead_store_elimination_pass => LLVMAddDeadStoreEliminationPass;
+            /// Simplifies the control flow graph (`simplifycfg`)
+            fn add_cfg_simplification_pass => LLVMAddCFGSimplificationPass;
+            /// Threads jumps over blocks with known conditions
+            /// (`jump-threading`)
+            fn add_jump_threading_pass => LLVMAddJumpThreadingPass;
+            /// Propagates constants (`sccp`)
+            fn add_sccp_pass => LLVMAddSCCPPass;
+            /// Propagates value ranges (`correlated-propagation`)
+            fn add_correlated_value_propagation_pass => LLVMAddCorrelatedValuePropagationPass;
+            /// Reassociates commutative expressions (`reassociate`)
+            fn add_reassociate_pass => LLVMAddReassociatePass;
+            /// Optimizes memcpy and memset calls (`memcpyopt`)
+            fn add_mem_cpy_opt_pass => LLVMAddMemCpyOptPass;
+            /// Eliminates tail calls (`tailcallelim`)
+            fn add_tail_call_elimination_pass => LLVMAddTailCallEliminationPass;
+            /// Moves loop invariant code out of loops (`licm`)
+            fn add_licm_pass => LLVMAddLICMPass;
+            /// Deletes dead loops (`loop-deletion`)
+            fn add_loop_deletion_pass => LLVMAddLoopDeletionPass;
+            /// Recognizes loop idioms, e.g. memset loops (`loop-idiom`)
+            fn add_loop_idiom_pass => LLVMAddLoopIdiomPass;
+            /// Rotates loops (`loop-rotate`)
+            fn add_loop_rotate_pass => LLVMAddLoopRotatePass;
+            /// Rerolls unrolled loops (`loop-reroll`)
+            fn add_loop_reroll_pass => LLVMAddLoopRerollPass;
+            /// Unrolls loops (`loop-unroll`)
+            fn add_loop_unroll_pass => LLVMAddLoopUnrollPass;
+            /// Unrolls outer loops and fuses their inner loops
+            /// (`loop-unroll-and-jam`)
+            fn add_loop_unroll_and_jam_pass => LLVMAddLoopUnrollAndJamPass;
+            /// Hoists loop invariant conditions out of loops (`loop-unswitch`)
+            fn add_loop_unswitch_pass => LLVMAddLoopUnswitchPass;
+            /// Canonicalizes induction variables (`indvars`)
+            fn add_ind_var_simplify_pass => LLVMAddIndVarSimplifyPass;
+            /// Vectorizes loops (`loop-vectorize`)
+            fn add_loop_vectorize_pass => LLVMAddLoopVectorizePass;
+            /// Vectorizes straight line code (`slp-vectorizer`)
+            fn add_slp_vectorize_pass => LLVMAddSLPVectorizePass;
+            /// Lowers switches to branches (`lowerswitch`)
+            fn add_lower_switch_pass => LLVMAddLowerSwitchPass;
+            /// Lowers `llvm.expect` intrinsics (`lower-expect`)
+            fn add_lower_expect_intrinsic_pass => LLVMAddLowerExpectIntrinsicPass;
+            /// Runs the verifier on the IR (`verify`)
+            fn add_verifier_pass => LLVMAddVerifierPass;
+            /// Adds basic alias analysis for the following passes
+            fn add_basic_alias_analysis_pass => LLVMAddBasicAliasAnalysisPass;
+            /// Adds type based alias analysis for the following passes
+            fn add_type_based_alias_analysis_pass => LLVMAddTypeBasedAliasAnalysisPass;
+        }
+    }
+}
 
+/// A pass manager running its passes on whole modules.
+///
+/// ```rust
+/// use llvm::{Context, PassManager};
+///
+/// let context = Context::new();
+/// let mut module = context.parse_ir("
+///     define internal void @unused() {
+///       ret void
+///     }
+/// ").unwrap();
+///
+/// let mut pm = PassManager::new();
+/// pm.add_global_dce_pass();
+/// assert!(pm.run(&mut module));
+/// assert!(module.get_named_function("unused").is_none());
+/// ```
 #[derive(Debug)]
 pub struct PassManager {
     pub ptr: LLVMPassManagerRef,
@@ -13,4 +139,473 @@ impl PassManager {
             ptr: unsafe { llvm::LLVMCreatePassManager() }
         }
     }
+
+    /// Runs the passes on `module`, returning whether it was modified.
+    pub fn run(&mut self, module: &mut Module) -> bool {
+        unsafe {
+            llvm::LLVMRunPassManager(self.ptr, module.ptr) != 0
+        }
+    }
+
+    add_function_passes!();
+
+    add_passes! {
+        /// Inlines functions (`inline`)
+        fn add_function_inlining_pass => LLVMAddFunctionInliningPass;
+        /// Inlines `alwaysinline` functions (`always-inline`)
+        fn add_always_inliner_pass => LLVMAddAlwaysInlinerPass;
+        /// Promotes by-reference arguments to scalars (`argpromotion`)
+        fn add_argument_promotion_pass => LLVMAddArgumentPromotionPass;
+        /// Infers function attributes (`function-attrs`)
+        fn add_function_attrs_pass => LLVMAddFunctionAttrsPass;
+        /// Eliminates dead arguments (`deadargelim`)
+        fn add_dead_arg_elimination_pass => LLVMAddDeadArgEliminationPass;
+        /// Eliminates unused internal globals (`globaldce`)
+        fn add_global_dce_pass => LLVMAddGlobalDCEPass;
+        /// Optimizes global variables (`globalopt`)
+        fn add_global_optimizer_pass => LLVMAddGlobalOptimizerPass;
+        /// Propagates constants across functions (`ipsccp`)
+        fn add_ipsccp_pass => LLVMAddIPSCCPPass;
+        /// Merges duplicate constants (`constmerge`)
+        fn add_constant_merge_pass => LLVMAddConstantMergePass;
+        /// Merges identical functions (`mergefunc`)
+        fn add_merge_functions_pass => LLVMAddMergeFunctionsPass;
+        /// Removes unused exception handling info (`prune-eh`)
+        fn add_prune_eh_pass => LLVMAddPruneEHPass;
+        /// Removes unused function declarations (`strip-dead-prototypes`)
+        fn add_strip_dead_prototypes_pass => LLVMAddStripDeadPrototypesPass;
+        /// Strips symbols from the module (`strip`)
+        fn add_strip_symbols_pass => LLVMAddStripSymbolsPass;
+    }
+
+    /// Gives all functions and globals internal linkage, except for `main`
+    /// if `all_but_main` is set (`internalize`)
+    pub fn add_internalize_pass(&mut self, all_but_main: bool) {
+        unsafe {
+            LLVMAddInternalizePass(self.ptr, all_but_main as u32)
+        }
+    }
+}
+
+impl Default for PassManager {
+    /// Creates a new pass manager
+    fn default() -> Self {
+        PassManager::new()
+    }
+}
+
+impl Drop for PassManager {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposePassManager(self.ptr);
+        }
+    }
+}
+
+/// A pass manager running its passes on single functions of a module, e.g.
+/// right after they're generated.
+///
+/// The pass manager borrows the module it was created for, so it can't
+/// outlive it:
+///
+/// ```rust,compile_fail,E0505
+/// let context = llvm::Context::new();
+/// let module = context.module_create_with_name("module");
+/// let mut fpm = llvm::FunctionPassManager::for_module(&module);
+/// drop(module); // error: cannot move out of `module` because it is borrowed
+/// fpm.initialize();
+/// ```
+///
+/// ```rust
+/// use llvm::{Context, FunctionPassManager};
+///
+/// let context = Context::new();
+/// let module = context.parse_ir("
+///     define i32 @f(i32 %x) {
+///       %y = add i32 %x, 0
+///       ret i32 %y
+///     }
+/// ").unwrap();
+/// let f = module.get_named_function("f").unwrap();
+///
+/// let mut fpm = FunctionPassManager::for_module(&module);
+/// fpm.add_instruction_combining_pass();
+/// fpm.initialize();
+/// assert!(fpm.run_on(&f));
+/// fpm.finalize();
+/// assert!(!module.to_string().contains("add"));
+/// ```
+#[derive(Debug)]
+pub struct FunctionPassManager<'m> {
+    pub ptr: LLVMPassManagerRef,
+    _marker: PhantomData<Cell<&'m ()>>,
+}
+impl_llvm_ref!(FunctionPassManager<'m>, LLVMPassManagerRef);
+
+impl<'m> FunctionPassManager<'m> {
+    /// Creates a pass manager for the functions of `module`, which stays
+    /// borrowed until the pass manager is dropped.
+    pub fn for_module(module: &'m Module) -> FunctionPassManager<'m> {
+        unsafe {
+            llvm::LLVMCreateFunctionPassManagerForModule(module.ptr).into()
+        }
+    }
+
+    /// Initializes the passes, which must be done before running them.
+    /// Returns whether the module was modified.
+    pub fn initialize(&mut self) -> bool {
+        unsafe {
+            llvm::LLVMInitializeFunctionPassManager(self.ptr) != 0
+        }
+    }
+
+    /// Runs the passes on `function`, returning whether it was modified.
+    pub fn run_on(&mut self, function: &Function<'m>) -> bool {
+        unsafe {
+            llvm::LLVMRunFunctionPassManager(self.ptr, function.ptr) != 0
+        }
+    }
+
+    /// Finalizes the passes after they were run. Returns whether the module
+    /// was modified.
+    pub fn finalize(&mut self) -> bool {
+        unsafe {
+            llvm::LLVMFinalizeFunctionPassManager(self.ptr) != 0
+        }
+    }
+
+    add_function_passes!();
+}
+
+impl<'m> Drop for FunctionPassManager<'m> {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposePassManager(self.ptr);
+        }
+    }
+}
+
+/// The optimization levels of `clang` and `opt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptPreset {
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+    Oz,
+}
+
+/// Populates pass managers with LLVM's standard optimization pipeline.
+///
+/// ```rust
+/// use llvm::{Context, OptPreset, PassManager, PassManagerBuilder};
+///
+/// let context = Context::new();
+/// let mut module = context.parse_ir("
+///     define i32 @f(i32 %x) {
+///       %a = add i32 %x, 1
+///       %b = add i32 %a, 1
+///       ret i32 %b
+///     }
+/// ").unwrap();
+///
+/// let mut pm = PassManager::new();
+/// PassManagerBuilder::preset(OptPreset::O2)
+///     .inliner_threshold(Some(300))
+///     .populate_module_pass_manager(&mut pm);
+/// pm.run(&mut module);
+/// assert!(module.to_string().contains("add i32 %x, 2"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassManagerBuilder {
+    opt_level: u32,
+    size_level: u32,
+    inliner_threshold: Option<u32>,
+    unroll_loops: bool,
+    loop_vectorize: bool,
+    slp_vectorize: bool,
+}
+
+impl PassManagerBuilder {
+    /// Creates a builder for `-O0`, which doesn't optimize.
+    pub fn new() -> PassManagerBuilder {
+        PassManagerBuilder {
+            opt_level: 0,
+            size_level: 0,
+            inliner_threshold: None,
+            unroll_loops: true,
+            loop_vectorize: false,
+            slp_vectorize: false,
+        }
+    }
+
+    /// Creates a builder with the same settings `clang` uses for `preset`.
+    pub fn preset(preset: OptPreset) -> PassManagerBuilder {
+        let (opt_level, size_level, inliner_threshold) = match preset {
+            OptPreset::O0 => (0, 0, None),
+            OptPreset::O1 => (1, 0, None),
+            OptPreset::O2 => (2, 0, Some(225)),
+            OptPreset::O3 => (3, 0, Some(250)),
+            OptPreset::Os => (2, 1, Some(75)),
+            OptPreset::Oz => (2, 2, Some(25)),
+        };
+        let vectorize = opt_level > 1 && size_level < 2;
+
+        PassManagerBuilder {
+            opt_level: opt_level,
+            size_level: size_level,
+            inliner_threshold: inliner_threshold,
+            unroll_loops: size_level == 0,
+            loop_vectorize: vectorize,
+            slp_vectorize: vectorize,
+        }
+    }
+
+    /// Sets the optimization level, from 0 to 3.
+    pub fn opt_level(mut self, level: u32) -> PassManagerBuilder {
+        self.opt_level = level;
+        self
+    }
+
+    /// Sets how much to optimize for size, from 0 (not at all) to 2.
+    pub fn size_level(mut self, level: u32) -> PassManagerBuilder {
+        self.size_level = level;
+        self
+    }
+
+    /// Sets the cost threshold below which calls are inlined, or disables
+    /// the inliner with `None`.
+    pub fn inliner_threshold(mut self, threshold: Option<u32>) -> PassManagerBuilder {
+        self.inliner_threshold = threshold;
+        self
+    }
+
+    /// Sets whether loops are unrolled.
+    pub fn unroll_loops(mut self, unroll: bool) -> PassManagerBuilder {
+        self.unroll_loops = unroll;
+        self
+    }
+
+    /// Sets whether the loop vectorizer is added to module pass managers.
+    pub fn loop_vectorize(mut self, vectorize: bool) -> PassManagerBuilder {
+        self.loop_vectorize = vectorize;
+        self
+    }
+
+    /// Sets whether the SLP vectorizer is added to module pass managers.
+    pub fn slp_vectorize(mut self, vectorize: bool) -> PassManagerBuilder {
+        self.slp_vectorize = vectorize;
+        self
+    }
+
+    // Runs `f` with an LLVM pass manager builder with these settings
+    fn with_llvm_builder<F: FnOnce(LLVMPassManagerBuilderRef)>(&self, f: F) {
+        unsafe {
+            let builder = LLVMPassManagerBuilderCreate();
+            LLVMPassManagerBuilderSetOptLevel(builder, self.opt_level);
+            LLVMPassManagerBuilderSetSizeLevel(builder, self.size_level);
+            LLVMPassManagerBuilderSetDisableUnrollLoops(builder, !self.unroll_loops as LLVMBool);
+            if let Some(threshold) = self.inliner_threshold {
+                LLVMPassManagerBuilderUseInlinerWithThreshold(builder, threshold);
+            }
+            f(builder);
+            LLVMPassManagerBuilderDispose(builder);
+        }
+    }
+
+    /// Adds the passes for these settings to a module pass manager.
+    pub fn populate_module_pass_manager(&self, pm: &mut PassManager) {
+        self.with_llvm_builder(|builder| unsafe {
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, pm.ptr)
+        });
+
+        // LLVM's C API can't enable the vectorizers of the pipeline, so they
+        // are added at its end, followed by the cleanups they need
+        if self.loop_vectorize {
+            pm.add_loop_vectorize_pass();
+        }
+        if self.slp_vectorize {
+            pm.add_slp_vectorize_pass();
+        }
+        if self.loop_vectorize || self.slp_vectorize {
+            pm.add_instruction_combining_pass();
+            pm.add_cfg_simplification_pass();
+        }
+    }
+
+    /// Adds the passes for these settings to a function pass manager, which
+    /// is meant to run before a module pass manager populated with the same
+    /// settings.
+    pub fn populate_function_pass_manager(&self, fpm: &mut FunctionPassManager) {
+        self.with_llvm_builder(|builder| unsafe {
+            LLVMPassManagerBuilderPopulateFunctionPassManager(builder, fpm.ptr)
+        });
+    }
+}
+
+impl Default for PassManagerBuilder {
+    /// Creates a builder for `-O0`
+    fn default() -> Self {
+        PassManagerBuilder::new()
+    }
+}
+
+/// Options for running pass pipelines with `Module::run_passes`.
+#[derive(Debug)]
+pub struct PassBuilderOptions {
+    pub ptr: LLVMPassBuilderOptionsRef,
+}
+impl_llvm_ref!(PassBuilderOptions, LLVMPassBuilderOptionsRef);
+
+impl PassBuilderOptions {
+    pub fn new() -> PassBuilderOptions {
+        unsafe {
+            LLVMCreatePassBuilderOptions().into()
+        }
+    }
+
+    /// Sets whether the verifier runs after each pass.
+    pub fn set_verify_each(&mut self, verify_each: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetVerifyEach(self.ptr, verify_each as LLVMBool)
+        }
+    }
+
+    /// Sets whether the pass manager prints what it's doing to stderr.
+    pub fn set_debug_logging(&mut self, debug_logging: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetDebugLogging(self.ptr, debug_logging as LLVMBool)
+        }
+    }
+
+    /// Sets whether the loop vectorizer interleaves loops in the default
+    /// pipelines.
+    pub fn set_loop_interleaving(&mut self, interleave: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetLoopInterleaving(self.ptr, interleave as LLVMBool)
+        }
+    }
+
+    /// Sets whether the default pipelines vectorize loops.
+    pub fn set_loop_vectorization(&mut self, vectorize: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetLoopVectorization(self.ptr, vectorize as LLVMBool)
+        }
+    }
+
+    /// Sets whether the default pipelines vectorize straight line code.
+    pub fn set_slp_vectorization(&mut self, vectorize: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetSLPVectorization(self.ptr, vectorize as LLVMBool)
+        }
+    }
+
+    /// Sets whether the default pipelines unroll loops.
+    pub fn set_loop_unrolling(&mut self, unroll: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetLoopUnrolling(self.ptr, unroll as LLVMBool)
+        }
+    }
+
+    /// Sets whether the default pipelines merge identical functions.
+    pub fn set_merge_functions(&mut self, merge: bool) {
+        unsafe {
+            LLVMPassBuilderOptionsSetMergeFunctions(self.ptr, merge as LLVMBool)
+        }
+    }
+}
+
+impl Default for PassBuilderOptions {
+    /// Creates options with LLVM's defaults
+    fn default() -> Self {
+        PassBuilderOptions::new()
+    }
+}
+
+impl Drop for PassBuilderOptions {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposePassBuilderOptions(self.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Context;
+
+    #[test]
+    fn can_run_function_passes_on_modules() {
+        let context = Context::new();
+        let mut module = context.parse_ir("
+            define i32 @f(i32 %x) {
+              %p = alloca i32
+              store i32 %x, i32* %p
+              %v = load i32, i32* %p
+              ret i32 %v
+            }
+        ").unwrap();
+
+        let mut pm = PassManager::new();
+        pm.add_promote_memory_to_register_pass();
+        assert!(pm.run(&mut module));
+        assert!(!module.to_string().contains("alloca"));
+        assert!(module.to_string().contains("ret i32 %x"));
+
+        // There's nothing left to do
+        assert!(!pm.run(&mut module));
+    }
+
+    const REDUNDANT: &'static str = "
+        define i32 @f(i32 %x) {
+          %p = alloca i32
+          store i32 %x, i32* %p
+          %a = load i32, i32* %p
+          %b = load i32, i32* %p
+          %c = mul i32 %a, 2
+          %d = mul i32 %b, 2
+          %e = add i32 %c, %d
+          ret i32 %e
+        }
+    ";
+
+    #[test]
+    fn o2_folds_redundant_computations() {
+        let context = Context::new();
+        let mut module = context.parse_ir(REDUNDANT).unwrap();
+        let builder = PassManagerBuilder::preset(OptPreset::O2);
+        {
+            let f = module.get_named_function("f").unwrap();
+            let mut fpm = FunctionPassManager::for_module(&module);
+            builder.populate_function_pass_manager(&mut fpm);
+            fpm.initialize();
+            fpm.run_on(&f);
+            fpm.finalize();
+        }
+        let mut pm = PassManager::new();
+        builder.populate_module_pass_manager(&mut pm);
+        pm.run(&mut module);
+
+        let ir = module.to_string();
+        assert!(ir.contains("shl i32 %x, 2"), "{}", ir);
+        for removed in &["alloca", "load", "mul", "add"] {
+            assert!(!ir.contains(&format!(" = {} ", removed)), "{}", ir);
+        }
+    }
+
+    #[test]
+    fn o0_keeps_redundant_computations() {
+        let context = Context::new();
+        let mut module = context.parse_ir(REDUNDANT).unwrap();
+
+        let mut pm = PassManager::new();
+        PassManagerBuilder::preset(OptPreset::O0).populate_module_pass_manager(&mut pm);
+        pm.run(&mut module);
+
+        let ir = module.to_string();
+        assert!(ir.contains("%b = load"), "{}", ir);
+        assert!(ir.contains("%d = mul"), "{}", ir);
+    }
 }
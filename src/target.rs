@@ -3,7 +3,11 @@ use llvm_sys::target_machine::*;
 use llvm_sys::target::*;
 use super::*;
 
-error_chain! {}
+error_chain! {
+    links {
+        Module(module::Error, module::ErrorKind);
+    }
+}
 
 #[derive(Debug)]
 pub struct Target {
@@ -30,27 +34,26 @@ impl Target {
                                  triple: &str,
                                  cpu: &str,
                                  features: &str,
-                                 level: LLVMCodeGenOptLevel,
-                                 reloc: LLVMRelocMode,
-                                 model: LLVMCodeModel) -> TargetMachine {
+                                 level: OptLevel,
+                                 reloc: RelocMode,
+                                 model: CodeModel) -> TargetMachine {
         TargetMachine::new(self, triple, cpu, features, level, reloc, model)
     }
 }
 
 #[derive(Debug)]
 pub struct TargetMachine {
-    ptr: LLVMTargetMachineRef,
+    pub ptr: LLVMTargetMachineRef,
 }
 
 impl TargetMachine {
-    // TODO: create wrappers for all these types
     pub fn new(target: &Target,
                triple: &str,
                cpu: &str,
                features: &str,
-               level: LLVMCodeGenOptLevel,
-               reloc: LLVMRelocMode,
-               model: LLVMCodeModel) -> TargetMachine {
+               level: OptLevel,
+               reloc: RelocMode,
+               model: CodeModel) -> TargetMachine {
 
         let c_triple = CString::new(triple).unwrap();
         let c_cpu = CString::new(cpu).unwrap();
@@ -61,9 +64,9 @@ impl TargetMachine {
                 c_triple.as_ptr(),
                 c_cpu.as_ptr(),
                 c_features.as_ptr(),
-                level,
-                reloc,
-                model
+                level.into(),
+                reloc.into(),
+                model.into()
             )
         };
 
@@ -72,10 +75,15 @@ impl TargetMachine {
         }
     }
 
+    /// Compiles `module` into a file at `path`.
+    ///
+    /// The module is verified first if that was enabled with
+    /// `Module::set_verify_before_codegen`.
     pub fn emit_to_file(&mut self,
                         module: &mut Module,
                         path: &str,
-                        file_type: LLVMCodeGenFileType) -> Result<()> {
+                        file_type: FileType) -> Result<()> {
+            module.verify_before_codegen()?;
             let c_path = CString::new(path).unwrap();
             let mut em: usize = 0;
             let em_ptr: *mut usize = &mut em;
@@ -83,7 +91,7 @@ impl TargetMachine {
                 LLVMTargetMachineEmitToFile(self.ptr,
                                             module.ptr,
                                             c_path.as_ptr() as *mut i8,
-                                            file_type,
+                                            file_type.into(),
                                             em_ptr as *mut *mut i8);
                 if em == 0 { // no error message was set
                     Ok(())
@@ -39,6 +39,9 @@ pub enum Kind<'a> {
     Metadata(&'a Metadata),
     X86_MMX(&'a X86_MMX),
     Token(&'a Token),
+    ScalableVector(&'a ScalableVector),
+    BFloat(&'a BFloat),
+    X86_AMX(&'a X86_AMX),
 }
 
 /// The "superclass" of the LLVM base types. Types can only be passed as
@@ -158,7 +161,7 @@ impl Type {
     /// }
     /// # }
     /// ```
-    pub fn downcast(&self) -> Kind {
+    pub fn downcast(&self) -> Kind<'_> {
         unsafe {
             match LLVMGetTypeKind(self.into()) {
                 LLVMTypeKind::LLVMVoidTypeKind => Kind::Void(transmute(self)),
@@ -178,6 +181,9 @@ impl Type {
                 LLVMTypeKind::LLVMMetadataTypeKind => Kind::Metadata(transmute(self)),
                 LLVMTypeKind::LLVMX86_MMXTypeKind => Kind::X86_MMX(transmute(self)),
                 LLVMTypeKind::LLVMTokenTypeKind => Kind::Token(transmute(self)),
+                LLVMTypeKind::LLVMScalableVectorTypeKind => Kind::ScalableVector(transmute(self)),
+                LLVMTypeKind::LLVMBFloatTypeKind => Kind::BFloat(transmute(self)),
+                LLVMTypeKind::LLVMX86_AMXTypeKind => Kind::X86_AMX(transmute(self)),
             }
         }
     }
@@ -200,6 +206,9 @@ impl Type {
         pub fn try_as_metadata -> Metadata
         pub fn try_as_x86_mmx -> X86_MMX
         pub fn try_as_token -> Token
+        pub fn try_as_scalable_vector -> ScalableVector
+        pub fn try_as_bfloat -> BFloat
+        pub fn try_as_x86_amx -> X86_AMX
     }
 }
 
@@ -299,6 +308,15 @@ impl_type!(X86_MMX, x86_mmx_transmutes_to_type_ref);
 pub struct Token(Type);
 impl_type!(Token, token_transmutes_to_type_ref);
 
+/// 16 bit brain floating point type
+pub struct BFloat(Type);
+impl_type!(BFloat, bfloat_transmutes_to_type_ref);
+
+/// X86 AMX tiles
+#[allow(non_camel_case_types)]
+pub struct X86_AMX(Type);
+impl_type!(X86_AMX, x86_amx_transmutes_to_type_ref);
+
 /// Aribitrary bit width integers
 pub struct Integer(Type);
 impl_type!(Integer, integer_transmutes_to_type_ref);
@@ -352,12 +370,16 @@ impl_type!(Pointer, pointer_transmutes_to_type_ref);
 pub struct Vector(Type);
 impl_type!(Vector, vector_transmutes_to_type_ref);
 
+/// Vector type whose size is a runtime multiple of its element count
+pub struct ScalableVector(Type);
+impl_type!(ScalableVector, scalable_vector_transmutes_to_type_ref);
+
 /// Trait marking types that can be represented as an LLVM type.
 pub trait ContextType {
     type LlvmType;
 
     /// Gets a reference to the corresponding LLVM type.
-    fn get_type_in_context<'a>(context: &'a Context) -> &'a Self::LlvmType;
+    fn get_type_in_context<'a>(context: &'a ContextRef) -> &'a Self::LlvmType;
 }
 
 macro_rules! impl_context_type {
@@ -365,15 +387,16 @@ macro_rules! impl_context_type {
         impl ContextType for $t {
             type LlvmType = $llvm_type;
 
-            fn get_type_in_context<'a>(context: &'a Context) -> &'a Self::LlvmType {
+            fn get_type_in_context<'a>(context: &'a ContextRef) -> &'a Self::LlvmType {
                 unsafe {
-                    $to_type_in_context(context.ptr).into()
+                    $to_type_in_context(context.into()).into()
                 }
             }
         }
     }
 }
 
+impl_context_type!(() => Void, LLVMVoidTypeInContext);
 impl_context_type!(bool => Integer, LLVMInt1TypeInContext);
 impl_context_type!(char => Integer, LLVMInt32TypeInContext);
 impl_context_type!(u8 => Integer, LLVMInt8TypeInContext);
@@ -1,3 +1,20 @@
+//! LLVM values.
+//!
+//! Everything that can be used as an operand in LLVM IR is a [`Value`]:
+//! constants, instructions, function arguments, basic blocks and so on. The
+//! subclasses of `Value` are modelled in the same way as [`types`], and can be
+//! downcast safely with [`Value::downcast`] or the [`try_as_*`] methods.
+//!
+//! [`Value`]: struct.Value.html
+//! [`types`]: ../types/index.html
+//! [`Value::downcast`]: struct.Value.html#method.downcast
+//! [`try_as_*`]: struct.Value.html#method.try_as_argument
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::transmute;
+use std::ops::Deref;
+
 use llvm_sys::prelude::*;
 use llvm_sys::core::*;
 use llvm_sys::*;
@@ -5,44 +22,1610 @@ use llvm_sys::*;
 use super::*;
 use types::ContextType;
 
+/// Enumeration of the concrete values of the LLVM value hierarchy. Used for
+/// safe downcasting of `Value`.
+///
+/// Each variant holds the most derived class that this crate knows about, so
+/// e.g. a `ConstantInt` is returned as `Kind::ConstantInt`, never as
+/// `Kind::Constant`. The `Constant` and `Instruction` variants are used for
+/// values that don't have a more specific wrapper yet.
+#[derive(Debug)]
+pub enum Kind<'a> {
+    Argument(&'a Argument),
+    BasicBlock(&'a BasicBlock),
+    InlineAsm(&'a InlineAsm),
+    Metadata(&'a MetadataAsValue),
+    Function(Function<'a>),
+    GlobalVariable(&'a GlobalVariable),
+    GlobalAlias(&'a GlobalAlias),
+    ConstantInt(&'a ConstantInt),
+    ConstantFP(&'a ConstantFP),
+    ConstantPointerNull(&'a ConstantPointerNull),
+    UndefValue(&'a UndefValue),
+    ConstantExpr(&'a ConstantExpr),
+    BlockAddress(&'a BlockAddress),
+    Constant(&'a Constant),
+    PhiNode(&'a PhiNode),
+    BinaryOperator(&'a BinaryOperator),
+    CallInst(&'a CallInst),
+    AllocaInst(&'a AllocaInst),
+    LoadInst(&'a LoadInst),
+    StoreInst(&'a StoreInst),
+    AtomicRMWInst(&'a AtomicRMWInst),
+    AtomicCmpXchgInst(&'a AtomicCmpXchgInst),
+    BranchInst(&'a BranchInst),
+    ReturnInst(&'a ReturnInst),
+    SwitchInst(&'a SwitchInst),
+    IndirectBrInst(&'a IndirectBrInst),
+    UnreachableInst(&'a UnreachableInst),
+    InvokeInst(&'a InvokeInst),
+    LandingPadInst(&'a LandingPadInst),
+    ResumeInst(&'a ResumeInst),
+    CatchSwitchInst(&'a CatchSwitchInst),
+    CatchPadInst(&'a CatchPadInst),
+    CleanupPadInst(&'a CleanupPadInst),
+    CatchReturnInst(&'a CatchReturnInst),
+    CleanupReturnInst(&'a CleanupReturnInst),
+    Instruction(&'a Instruction),
+}
+
+/// The "superclass" of all LLVM values. Values can only be passed as
+/// references, `&Value`s.
+///
+/// # Ownership
+///
+/// `Value`s are owned by the `Module` or `Context` that created them, and
+/// live as long as their owner does.
+///
+/// # Casting to and from Subclasses
+///
+/// This follows the same rules as [`Type`]: any "subclass", such as
+/// `ConstantInt`, is implicitly upcast into `Value` (or any class in
+/// between, like `Constant`) with zero cost. Downcasts are explicit and
+/// checked, using [`downcast`] or the [`try_as_*`] methods.
+///
+/// # Representation
+///
+/// `LLVMValueRef`s are `transmute`d into `&Value`s, exactly like
+/// `LLVMTypeRef`s are for [`Type`]. Converting an `LLVMValueRef` into a
+/// `&Value` with the `From` trait is not safe, since neither the lifetime nor
+/// the class of the value can be checked.
+///
+/// [`Type`]: ../types/struct.Type.html
+/// [`downcast`]: #method.downcast
+/// [`try_as_*`]: #method.try_as_argument
 pub struct Value(LLVMValue); // TODO: mark this as an unsized type
 impl_llvm_type_wrapper!(LLVMValueRef, Value);
 impl_llvm_type_eq!(LLVMValueRef, Value);
 impl_llvm_type_fmt!(Value, LLVMPrintValueToString);
 
+macro_rules! try_as_fns {
+    ($(pub fn $name:ident -> $class:ident, $is_a:ident)*) => {
+        $(
+            /// Attempt a downcast, returning `None` if `self` is not an
+            /// instance of the class requested.
+            ///
+            /// Unlike `downcast`, this also succeeds for classes that are
+            /// not the most derived class of `self`, e.g. a `PhiNode` can be
+            /// downcast to an `Instruction` with `try_as_instruction`.
+            pub fn $name(&self) -> Option<&$class> {
+                unsafe {
+                    if $is_a(self.into()).is_null() {
+                        None
+                    } else {
+                        Some(transmute::<&Value, &$class>(self))
+                    }
+                }
+            }
+        )*
+    }
+}
+
 impl Value {
-    pub fn set_name(&mut self, name: &str) {
-        let c_name = CString::new(name).unwrap();
+    pub fn set_name(&self, name: &str) {
+        unsafe {
+            LLVMSetValueName2(self.into(), name.as_ptr() as *const _, name.len());
+        }
+    }
+
+    /// Returns the name of this value, which is empty for unnamed values.
+    pub fn name(&self) -> String {
+        unsafe {
+            let mut len = 0;
+            let name = LLVMGetValueName2(self.into(), &mut len);
+            String::from_utf8_lossy(::std::slice::from_raw_parts(name as *const u8, len)).into_owned()
+        }
+    }
+
+    /// Returns the type of this value.
+    pub fn get_type(&self) -> &Type {
+        unsafe { LLVMTypeOf(self.into()).into() }
+    }
+
+    /// Downcast a `&Value`, returning a variant of `Kind` that contains the
+    /// most derived class of the value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # fn main() {
+    /// # let context = llvm::Context::new();
+    /// let value: &llvm::Value = context.cons(42u64); // upcast
+    ///
+    /// if let llvm::value::Kind::ConstantInt(c) = value.downcast() {
+    ///     assert_eq!(c.zext_value(), 42);
+    /// }
+    /// # }
+    /// ```
+    pub fn downcast(&self) -> Kind<'_> {
+        let ptr: LLVMValueRef = self.into();
+        unsafe {
+            if LLVMValueIsBasicBlock(ptr) != 0 {
+                Kind::BasicBlock(transmute(self))
+            } else if !LLVMIsAArgument(ptr).is_null() {
+                Kind::Argument(transmute(self))
+            } else if !LLVMIsAInlineAsm(ptr).is_null() {
+                Kind::InlineAsm(transmute(self))
+            } else if !LLVMIsAFunction(ptr).is_null() {
+                Kind::Function(Function::from_value_ref(ptr))
+            } else if !LLVMIsAGlobalVariable(ptr).is_null() {
+                Kind::GlobalVariable(transmute(self))
+            } else if !LLVMIsAGlobalAlias(ptr).is_null() {
+                Kind::GlobalAlias(transmute(self))
+            } else if !LLVMIsAConstantInt(ptr).is_null() {
+                Kind::ConstantInt(transmute(self))
+            } else if !LLVMIsAConstantFP(ptr).is_null() {
+                Kind::ConstantFP(transmute(self))
+            } else if !LLVMIsAConstantPointerNull(ptr).is_null() {
+                Kind::ConstantPointerNull(transmute(self))
+            } else if !LLVMIsAUndefValue(ptr).is_null() {
+                Kind::UndefValue(transmute(self))
+            } else if !LLVMIsAConstantExpr(ptr).is_null() {
+                Kind::ConstantExpr(transmute(self))
+            } else if !LLVMIsABlockAddress(ptr).is_null() {
+                Kind::BlockAddress(transmute(self))
+            } else if !LLVMIsAConstant(ptr).is_null() {
+                Kind::Constant(transmute(self))
+            } else if !LLVMIsAPHINode(ptr).is_null() {
+                Kind::PhiNode(transmute(self))
+            } else if !LLVMIsABinaryOperator(ptr).is_null() {
+                Kind::BinaryOperator(transmute(self))
+            } else if !LLVMIsACallInst(ptr).is_null() {
+                Kind::CallInst(transmute(self))
+            } else if !LLVMIsAAllocaInst(ptr).is_null() {
+                Kind::AllocaInst(transmute(self))
+            } else if !LLVMIsALoadInst(ptr).is_null() {
+                Kind::LoadInst(transmute(self))
+            } else if !LLVMIsAStoreInst(ptr).is_null() {
+                Kind::StoreInst(transmute(self))
+            } else if !LLVMIsAAtomicRMWInst(ptr).is_null() {
+                Kind::AtomicRMWInst(transmute(self))
+            } else if !LLVMIsAAtomicCmpXchgInst(ptr).is_null() {
+                Kind::AtomicCmpXchgInst(transmute(self))
+            } else if !LLVMIsABranchInst(ptr).is_null() {
+                Kind::BranchInst(transmute(self))
+            } else if !LLVMIsAReturnInst(ptr).is_null() {
+                Kind::ReturnInst(transmute(self))
+            } else if !LLVMIsASwitchInst(ptr).is_null() {
+                Kind::SwitchInst(transmute(self))
+            } else if !LLVMIsAIndirectBrInst(ptr).is_null() {
+                Kind::IndirectBrInst(transmute(self))
+            } else if !LLVMIsAUnreachableInst(ptr).is_null() {
+                Kind::UnreachableInst(transmute(self))
+            } else if !LLVMIsAInvokeInst(ptr).is_null() {
+                Kind::InvokeInst(transmute(self))
+            } else if !LLVMIsALandingPadInst(ptr).is_null() {
+                Kind::LandingPadInst(transmute(self))
+            } else if !LLVMIsAResumeInst(ptr).is_null() {
+                Kind::ResumeInst(transmute(self))
+            } else if !LLVMIsACatchSwitchInst(ptr).is_null() {
+                Kind::CatchSwitchInst(transmute(self))
+            } else if !LLVMIsACatchPadInst(ptr).is_null() {
+                Kind::CatchPadInst(transmute(self))
+            } else if !LLVMIsACleanupPadInst(ptr).is_null() {
+                Kind::CleanupPadInst(transmute(self))
+            } else if !LLVMIsACatchReturnInst(ptr).is_null() {
+                Kind::CatchReturnInst(transmute(self))
+            } else if !LLVMIsACleanupReturnInst(ptr).is_null() {
+                Kind::CleanupReturnInst(transmute(self))
+            } else if !LLVMIsAInstruction(ptr).is_null() {
+                Kind::Instruction(transmute(self))
+            } else {
+                // The only values left that can appear in IR are metadata
+                // wrapped as values
+                Kind::Metadata(transmute(self))
+            }
+        }
+    }
+
+    /// Attempt a downcast to a `BasicBlock`, returning `None` if `self` is
+    /// not a basic block.
+    pub fn try_as_basic_block(&self) -> Option<&BasicBlock> {
+        unsafe {
+            if LLVMValueIsBasicBlock(self.into()) == 0 {
+                None
+            } else {
+                Some(transmute::<&Value, &BasicBlock>(self))
+            }
+        }
+    }
+
+    /// Attempt a downcast to a `CallSite`, returning `None` if `self` is
+    /// neither a `call` nor an `invoke`.
+    pub fn try_as_call_site(&self) -> Option<&CallSite> {
+        if self.try_as_call_inst().is_some() || self.try_as_invoke_inst().is_some() {
+            Some(unsafe { transmute::<&Value, &CallSite>(self) })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt a downcast to a `Function`, returning `None` if `self` is
+    /// not a function.
+    pub fn try_as_function(&self) -> Option<Function<'_>> {
+        let ptr = unsafe { LLVMIsAFunction(self.into()) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { Function::from_value_ref(ptr) })
+        }
+    }
+
+    try_as_fns! {
+        pub fn try_as_argument -> Argument, LLVMIsAArgument
+        pub fn try_as_inline_asm -> InlineAsm, LLVMIsAInlineAsm
+        pub fn try_as_constant -> Constant, LLVMIsAConstant
+        pub fn try_as_global_value -> GlobalValue, LLVMIsAGlobalValue
+        pub fn try_as_global_variable -> GlobalVariable, LLVMIsAGlobalVariable
+        pub fn try_as_global_alias -> GlobalAlias, LLVMIsAGlobalAlias
+        pub fn try_as_constant_int -> ConstantInt, LLVMIsAConstantInt
+        pub fn try_as_constant_fp -> ConstantFP, LLVMIsAConstantFP
+        pub fn try_as_constant_pointer_null -> ConstantPointerNull, LLVMIsAConstantPointerNull
+        pub fn try_as_undef_value -> UndefValue, LLVMIsAUndefValue
+        pub fn try_as_constant_expr -> ConstantExpr, LLVMIsAConstantExpr
+        pub fn try_as_block_address -> BlockAddress, LLVMIsABlockAddress
+        pub fn try_as_instruction -> Instruction, LLVMIsAInstruction
+        pub fn try_as_phi_node -> PhiNode, LLVMIsAPHINode
+        pub fn try_as_binary_operator -> BinaryOperator, LLVMIsABinaryOperator
+        pub fn try_as_call_inst -> CallInst, LLVMIsACallInst
+        pub fn try_as_alloca_inst -> AllocaInst, LLVMIsAAllocaInst
+        pub fn try_as_load_inst -> LoadInst, LLVMIsALoadInst
+        pub fn try_as_store_inst -> StoreInst, LLVMIsAStoreInst
+        pub fn try_as_atomic_rmw_inst -> AtomicRMWInst, LLVMIsAAtomicRMWInst
+        pub fn try_as_atomic_cmpxchg_inst -> AtomicCmpXchgInst, LLVMIsAAtomicCmpXchgInst
+        pub fn try_as_branch_inst -> BranchInst, LLVMIsABranchInst
+        pub fn try_as_return_inst -> ReturnInst, LLVMIsAReturnInst
+        pub fn try_as_switch_inst -> SwitchInst, LLVMIsASwitchInst
+        pub fn try_as_indirect_br_inst -> IndirectBrInst, LLVMIsAIndirectBrInst
+        pub fn try_as_unreachable_inst -> UnreachableInst, LLVMIsAUnreachableInst
+        pub fn try_as_invoke_inst -> InvokeInst, LLVMIsAInvokeInst
+        pub fn try_as_landing_pad_inst -> LandingPadInst, LLVMIsALandingPadInst
+        pub fn try_as_resume_inst -> ResumeInst, LLVMIsAResumeInst
+        pub fn try_as_catch_switch_inst -> CatchSwitchInst, LLVMIsACatchSwitchInst
+        pub fn try_as_funclet_pad_inst -> FuncletPadInst, LLVMIsAFuncletPadInst
+        pub fn try_as_catch_pad_inst -> CatchPadInst, LLVMIsACatchPadInst
+        pub fn try_as_cleanup_pad_inst -> CleanupPadInst, LLVMIsACleanupPadInst
+        pub fn try_as_catch_return_inst -> CatchReturnInst, LLVMIsACatchReturnInst
+        pub fn try_as_cleanup_return_inst -> CleanupReturnInst, LLVMIsACleanupReturnInst
+    }
+}
+
+macro_rules! impl_value {
+    ($t:ident: $parent:ty, $test_name:ident) => {
+        impl Deref for $t {
+            type Target = $parent;
+
+            fn deref(&self) -> &Self::Target {
+                unsafe { transmute::<&Self, &Self::Target>(self) }
+            }
+        }
+
+        // This would not be needed if the compiler could infer that the
+        // From<&Value> for LLVMValueRef above worked on &SubValue.
+        impl<'a> From<&'a $t> for LLVMValueRef {
+            fn from(val: &'a $t) -> LLVMValueRef {
+                unsafe { transmute::<&$t, LLVMValueRef>(val) }
+            }
+        }
+
+        impl<'a> From<LLVMValueRef> for &'a $t {
+            fn from(ptr: LLVMValueRef) -> &'a $t {
+                unsafe { transmute::<LLVMValueRef, &$t>(ptr) }
+            }
+        }
+
+        impl_llvm_type_eq!(LLVMValueRef, $t);
+
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let v: &Value = self;
+                fmt::Display::fmt(v, f)
+            }
+        }
+
+        impl fmt::Debug for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "llvm::value::{}({})", stringify!($t), self)
+            }
+        }
+
+        #[test]
+        fn $test_name() {
+            use std::mem;
+            assert_eq!(mem::align_of::<LLVMValueRef>(), mem::align_of::<&$t>());
+            assert_eq!(mem::size_of::<LLVMValueRef>(), mem::size_of::<&$t>());
+        }
+    }
+}
+
+/// A formal argument of a function
+pub struct Argument(Value);
+impl_value!(Argument: Value, argument_transmutes_to_value_ref);
+
+impl Argument {
+    /// Returns the function this argument belongs to.
+    pub fn parent(&self) -> Function<'_> {
+        unsafe { Function::from_value_ref(LLVMGetParamParent(self.into())) }
+    }
+}
+
+/// A basic block, i.e. a list of instructions ending with a terminator
+pub struct BasicBlock(Value);
+impl_value!(BasicBlock: Value, basic_block_transmutes_to_value_ref);
+
+impl<'a> From<LLVMBasicBlockRef> for &'a BasicBlock {
+    fn from(ptr: LLVMBasicBlockRef) -> &'a BasicBlock {
+        unsafe { LLVMBasicBlockAsValue(ptr).into() }
+    }
+}
+
+impl<'a> From<&'a BasicBlock> for LLVMBasicBlockRef {
+    fn from(bb: &'a BasicBlock) -> LLVMBasicBlockRef {
+        unsafe { LLVMValueAsBasicBlock(bb.into()) }
+    }
+}
+
+impl BasicBlock {
+    /// Returns the function this basic block is inserted in, if any.
+    pub fn parent(&self) -> Option<Function<'_>> {
+        let p = unsafe { LLVMGetBasicBlockParent(self.into()) };
+        if p.is_null() {
+            None
+        } else {
+            Some(unsafe { Function::from_value_ref(p) })
+        }
+    }
+
+    /// Returns the terminator instruction of this basic block, or `None` if
+    /// the block is not well formed yet.
+    pub fn terminator(&self) -> Option<&Instruction> {
+        let p = unsafe { LLVMGetBasicBlockTerminator(self.into()) };
+        if p.is_null() {
+            None
+        } else {
+            Some(p.into())
+        }
+    }
+
+    /// Returns an iterator over the instructions in this basic block.
+    pub fn instructions(&self) -> InstructionIter<'_> {
+        InstructionIter {
+            next: unsafe { LLVMGetFirstInstruction(self.into()) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the address of this basic block, which can be stored and
+    /// branched to with `indirectbr`.
+    ///
+    /// Returns `None` if the block isn't inserted in a function, or if it's
+    /// the entry block of its function, which has no address.
+    pub fn address(&self) -> Option<&BlockAddress> {
+        let function = self.parent()?;
+        if unsafe { LLVMGetEntryBasicBlock(function.ptr) } == LLVMBasicBlockRef::from(self) {
+            return None;
+        }
+        unsafe { Some(LLVMBlockAddress(function.ptr, self.into()).into()) }
+    }
+}
+
+#[derive(Debug)]
+pub struct InstructionIter<'a> {
+    next: LLVMValueRef,
+    _marker: PhantomData<&'a Instruction>,
+}
+
+impl<'a> Iterator for InstructionIter<'a> {
+    type Item = &'a Instruction;
+
+    fn next(&mut self) -> Option<&'a Instruction> {
+        if self.next.is_null() {
+            None
+        } else {
+            let inst = self.next;
+            self.next = unsafe { LLVMGetNextInstruction(inst) };
+            Some(inst.into())
+        }
+    }
+}
+
+/// An inline assembler expression
+pub struct InlineAsm(Value);
+impl_value!(InlineAsm: Value, inline_asm_transmutes_to_value_ref);
+
+/// A metadata node used as an operand, e.g. of an intrinsic call
+pub struct MetadataAsValue(Value);
+impl_value!(MetadataAsValue: Value, metadata_as_value_transmutes_to_value_ref);
+
+// Constants:
+
+/// A value that is known at compile time
+pub struct Constant(Value);
+impl_value!(Constant: Value, constant_transmutes_to_value_ref);
+
+impl Constant {
+    /// Returns true if this is the null value of its type, e.g. `0`, `0.0`
+    /// or `null`.
+    pub fn is_null(&self) -> bool {
+        unsafe { LLVMIsNull(self.into()) == 1 }
+    }
+}
+
+/// Integer constants of any width
+pub struct ConstantInt(Constant);
+impl_value!(ConstantInt: Constant, constant_int_transmutes_to_value_ref);
+
+impl ConstantInt {
+    /// Returns the value of this constant, zero extended to 64 bits.
+    pub fn zext_value(&self) -> u64 {
+        unsafe { LLVMConstIntGetZExtValue(self.into()) }
+    }
+
+    /// Returns the value of this constant, sign extended to 64 bits.
+    pub fn sext_value(&self) -> i64 {
+        unsafe { LLVMConstIntGetSExtValue(self.into()) }
+    }
+}
+
+/// Floating point constants
+pub struct ConstantFP(Constant);
+impl_value!(ConstantFP: Constant, constant_fp_transmutes_to_value_ref);
+
+impl ConstantFP {
+    /// Returns the value of this constant as an `f64`, and whether precision
+    /// was lost in the conversion.
+    pub fn double_value(&self) -> (f64, bool) {
+        let mut loses_info = 0;
+        let v = unsafe { LLVMConstRealGetDouble(self.into(), &mut loses_info) };
+        (v, loses_info != 0)
+    }
+}
+
+/// The `null` pointer constant
+pub struct ConstantPointerNull(Constant);
+impl_value!(ConstantPointerNull: Constant, constant_pointer_null_transmutes_to_value_ref);
+
+/// The `undef` constant
+pub struct UndefValue(Constant);
+impl_value!(UndefValue: Constant, undef_value_transmutes_to_value_ref);
+
+/// Constant expressions, e.g. a `getelementptr` on a global
+pub struct ConstantExpr(Constant);
+impl_value!(ConstantExpr: Constant, constant_expr_transmutes_to_value_ref);
+
+/// The address of a basic block, see `BasicBlock::address`
+pub struct BlockAddress(Constant);
+impl_value!(BlockAddress: Constant, block_address_transmutes_to_value_ref);
+
+/// Functions, global variables and aliases
+pub struct GlobalValue(Constant);
+impl_value!(GlobalValue: Constant, global_value_transmutes_to_value_ref);
+
+/// Global variables
+pub struct GlobalVariable(GlobalValue);
+impl_value!(GlobalVariable: GlobalValue, global_variable_transmutes_to_value_ref);
+
+impl GlobalVariable {
+    /// Returns the initializer of this global, or `None` if it is only a
+    /// declaration.
+    pub fn initializer(&self) -> Option<&Constant> {
+        let p = unsafe { LLVMGetInitializer(self.into()) };
+        if p.is_null() {
+            None
+        } else {
+            Some(p.into())
+        }
+    }
+
+    pub fn set_initializer(&self, value: &Constant) {
+        unsafe { LLVMSetInitializer(self.into(), value.into()) }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        unsafe { LLVMIsGlobalConstant(self.into()) == 1 }
+    }
+
+    pub fn set_constant(&self, is_constant: bool) {
+        unsafe { LLVMSetGlobalConstant(self.into(), is_constant as LLVMBool) }
+    }
+}
+
+/// Global aliases
+pub struct GlobalAlias(GlobalValue);
+impl_value!(GlobalAlias: GlobalValue, global_alias_transmutes_to_value_ref);
+
+// Allows APIs to take any kind of global value, including `Function`s
+impl AsRef<GlobalValue> for GlobalValue {
+    fn as_ref(&self) -> &GlobalValue {
+        self
+    }
+}
+
+impl AsRef<GlobalValue> for GlobalVariable {
+    fn as_ref(&self) -> &GlobalValue {
+        self
+    }
+}
+
+impl AsRef<GlobalValue> for GlobalAlias {
+    fn as_ref(&self) -> &GlobalValue {
+        self
+    }
+}
+
+// Instructions:
+
+/// An instruction that was removed from its basic block with
+/// `Instruction::remove_from_parent`.
+///
+/// It must be inserted again with `Builder::insert_into_builder`, or it's
+/// deleted when it's dropped or `erase`d, as allowed by the contract of
+/// `remove_from_parent`. The uses left of a deleted instruction are replaced
+/// with `undef`.
+#[derive(Debug)]
+#[must_use = "a detached instruction is deleted unless it's inserted again"]
+pub struct DetachedInstruction<'a> {
+    instruction: &'a Instruction,
+    // The block the instruction was removed from
+    parent: &'a BasicBlock,
+}
+
+impl<'a> DetachedInstruction<'a> {
+    /// Deletes the instruction.
+    pub fn erase(self) {
+        drop(self)
+    }
+
+    // Returns the instruction, which is no longer deleted when this is
+    // dropped
+    pub(crate) fn into_instruction(self) -> &'a Instruction {
+        let instruction = self.instruction;
+        ::std::mem::forget(self);
+        instruction
+    }
+}
+
+impl<'a> Deref for DetachedInstruction<'a> {
+    type Target = Instruction;
+
+    fn deref(&self) -> &Instruction {
+        self.instruction
+    }
+}
+
+impl<'a> Drop for DetachedInstruction<'a> {
+    fn drop(&mut self) {
+        // The C API can only delete instructions that are in a block, so it's
+        // put back into its old one first
+        unsafe {
+            let ptr: LLVMValueRef = self.instruction.into();
+            let ty = LLVMTypeOf(ptr);
+            let builder = LLVMCreateBuilderInContext(LLVMGetTypeContext(ty));
+            LLVMPositionBuilderAtEnd(builder, self.parent.into());
+            LLVMInsertIntoBuilder(builder, ptr);
+            LLVMDisposeBuilder(builder);
+            LLVMReplaceAllUsesWith(ptr, LLVMGetUndef(ty));
+            LLVMInstructionEraseFromParent(ptr);
+        }
+    }
+}
+
+/// The "superclass" of all instructions
+pub struct Instruction(Value);
+impl_value!(Instruction: Value, instruction_transmutes_to_value_ref);
+
+impl Instruction {
+    /// Returns the basic block this instruction is inserted in, if any.
+    pub fn parent(&self) -> Option<&BasicBlock> {
+        let p = unsafe { LLVMGetInstructionParent(self.into()) };
+        if p.is_null() {
+            None
+        } else {
+            Some(p.into())
+        }
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        unsafe { LLVMGetInstructionOpcode(self.into()).into() }
+    }
+
+    /// Removes this instruction from its basic block without deleting it, so
+    /// it can be inserted elsewhere with `Builder::insert_into_builder`. The
+    /// instruction loses its name.
+    ///
+    /// # Safety
+    ///
+    /// The instruction is deleted if the returned `DetachedInstruction` is
+    /// erased or dropped rather than inserted again, while references to it
+    /// are `Copy` and may still be around. It must not be used afterwards
+    /// through any of them, and no builder or `InsertPointGuard` may be
+    /// positioned before it then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instruction isn't in a basic block.
+    pub unsafe fn remove_from_parent(&self) -> DetachedInstruction<'_> {
+        let parent = self.parent().expect("instruction is not in a basic block");
+        LLVMInstructionRemoveFromParent(self.into());
+        DetachedInstruction {
+            instruction: self,
+            parent: parent,
+        }
+    }
+
+    /// Returns whether this instruction can have fast math flags, which is
+    /// the case for floating point arithmetic and comparisons, and for
+    /// `phi`s, `select`s and calls with a floating point result.
+    pub fn supports_fast_math_flags(&self) -> bool {
+        match self.opcode() {
+            Opcode::FNeg | Opcode::FAdd | Opcode::FSub | Opcode::FMul | Opcode::FDiv |
+            Opcode::FRem | Opcode::FCmp => true,
+            Opcode::Phi | Opcode::Select | Opcode::Call => unsafe {
+                let mut ty = LLVMTypeOf(self.into());
+                while LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMArrayTypeKind {
+                    ty = LLVMGetElementType(ty);
+                }
+                if let LLVMTypeKind::LLVMVectorTypeKind |
+                       LLVMTypeKind::LLVMScalableVectorTypeKind = LLVMGetTypeKind(ty) {
+                    ty = LLVMGetElementType(ty);
+                }
+                matches!(LLVMGetTypeKind(ty),
+                         LLVMTypeKind::LLVMHalfTypeKind | LLVMTypeKind::LLVMBFloatTypeKind |
+                         LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind |
+                         LLVMTypeKind::LLVMX86_FP80TypeKind | LLVMTypeKind::LLVMFP128TypeKind |
+                         LLVMTypeKind::LLVMPPC_FP128TypeKind)
+            },
+            _ => false,
+        }
+    }
+}
+
+// The C API of LLVM 14 can't access fast math flags, see `ffi`
+impl Instruction {
+    /// Returns the fast math flags of this instruction, which are empty if
+    /// it can't have any.
+    ///
+    /// Fast math flags are read and written through LLVM's C++ API, which
+    /// is why this crate doesn't support MSVC targets.
+    pub fn fast_math_flags(&self) -> FastMathFlags {
+        if self.supports_fast_math_flags() {
+            FastMathFlags::from_bits_truncate(unsafe { ffi::LLVMGetFastMathFlags(self.into()) })
+        } else {
+            FastMathFlags::empty()
+        }
+    }
+
+    /// Replaces the fast math flags of this instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this instruction can't have fast math flags, see
+    /// `supports_fast_math_flags`.
+    pub fn set_fast_math_flags(&self, flags: FastMathFlags) {
+        assert!(self.supports_fast_math_flags(), "`{}` can't have fast math flags", self.opcode());
+        unsafe { ffi::LLVMSetFastMathFlags(self.into(), flags.bits()) }
+    }
+}
+
+/// `phi` instructions
+pub struct PhiNode(Instruction);
+impl_value!(PhiNode: Instruction, phi_node_transmutes_to_value_ref);
+
+impl PhiNode {
+    /// Adds incoming values to this `phi`, each paired with the basic block
+    /// it comes from.
+    pub fn add_incoming(&self, incoming: &[(&Value, &BasicBlock)]) {
+        let mut values: Vec<LLVMValueRef> = incoming.iter().map(|&(v, _)| v.into()).collect();
+        let mut blocks: Vec<LLVMBasicBlockRef> = incoming.iter().map(|&(_, bb)| bb.into()).collect();
+        unsafe {
+            LLVMAddIncoming(self.into(),
+                            values.as_mut_ptr(),
+                            blocks.as_mut_ptr(),
+                            incoming.len() as u32);
+        }
+    }
+
+    pub fn count_incoming(&self) -> u32 {
+        unsafe { LLVMCountIncoming(self.into()) }
+    }
+
+    /// Returns the incoming value and basic block at `index`.
+    pub fn get_incoming(&self, index: u32) -> Option<(&Value, &BasicBlock)> {
+        if index >= self.count_incoming() {
+            None
+        } else {
+            unsafe {
+                Some((LLVMGetIncomingValue(self.into(), index).into(),
+                      LLVMGetIncomingBlock(self.into(), index).into()))
+            }
+        }
+    }
+}
+
+/// Binary arithmetic and bitwise instructions, see `Builder::build_binop`
+pub struct BinaryOperator(Instruction);
+impl_value!(BinaryOperator: Instruction, binary_operator_transmutes_to_value_ref);
+
+impl BinaryOperator {
+    pub fn op(&self) -> BinaryOp {
+        BinaryOp::from_opcode(self.opcode()).unwrap()
+    }
+
+    // Panics unless this can have `flag`, which `ffi` relies on
+    fn check_flag(&self, flag: BinaryOpFlag) {
+        let op = self.op();
+        assert!(op.supports_flag(flag), "`{}` can't have the {} flag", op, flag);
+    }
+}
+
+// The C API of LLVM 14 can't access these flags, see `ffi`
+impl BinaryOperator {
+    /// Returns whether this has the `nsw` flag, which makes signed overflow
+    /// a poison value.
+    pub fn has_no_signed_wrap(&self) -> bool {
+        self.op().supports_flag(BinaryOpFlag::NoSignedWrap) &&
+            unsafe { ffi::LLVMGetNSW(self.into()) }
+    }
+
+    /// Returns whether this has the `nuw` flag, which makes unsigned
+    /// overflow a poison value.
+    pub fn has_no_unsigned_wrap(&self) -> bool {
+        self.op().supports_flag(BinaryOpFlag::NoUnsignedWrap) &&
+            unsafe { ffi::LLVMGetNUW(self.into()) }
+    }
+
+    /// Returns whether this has the `exact` flag, which makes the result a
+    /// poison value if the division has a remainder or the shift shifts out
+    /// non-zero bits.
+    pub fn is_exact(&self) -> bool {
+        self.op().supports_flag(BinaryOpFlag::Exact) && unsafe { ffi::LLVMGetExact(self.into()) }
+    }
+
+    /// Returns whether this has `flag`.
+    pub fn has_flag(&self, flag: BinaryOpFlag) -> bool {
+        match flag {
+            BinaryOpFlag::NoSignedWrap => self.has_no_signed_wrap(),
+            BinaryOpFlag::NoUnsignedWrap => self.has_no_unsigned_wrap(),
+            BinaryOpFlag::Exact => self.is_exact(),
+        }
+    }
+
+    /// Sets or clears the `nsw` flag.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless this is an `add`, `sub`, `mul` or `shl`.
+    pub fn set_no_signed_wrap(&self, enabled: bool) {
+        self.check_flag(BinaryOpFlag::NoSignedWrap);
+        unsafe { ffi::LLVMSetNSW(self.into(), enabled) }
+    }
+
+    /// Sets or clears the `nuw` flag.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless this is an `add`, `sub`, `mul` or `shl`.
+    pub fn set_no_unsigned_wrap(&self, enabled: bool) {
+        self.check_flag(BinaryOpFlag::NoUnsignedWrap);
+        unsafe { ffi::LLVMSetNUW(self.into(), enabled) }
+    }
+
+    /// Sets or clears the `exact` flag.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless this is a `udiv`, `sdiv`, `lshr` or `ashr`.
+    pub fn set_exact(&self, enabled: bool) {
+        self.check_flag(BinaryOpFlag::Exact);
+        unsafe { ffi::LLVMSetExact(self.into(), enabled) }
+    }
+
+    /// Sets or clears `flag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this can't have `flag`, see `BinaryOp::supports_flag`.
+    pub fn set_flag(&self, flag: BinaryOpFlag, enabled: bool) {
+        match flag {
+            BinaryOpFlag::NoSignedWrap => self.set_no_signed_wrap(enabled),
+            BinaryOpFlag::NoUnsignedWrap => self.set_no_unsigned_wrap(enabled),
+            BinaryOpFlag::Exact => self.set_exact(enabled),
+        }
+    }
+}
+
+/// The "superclass" of `call` and `invoke` instructions
+pub struct CallSite(Instruction);
+impl_value!(CallSite: Instruction, call_site_transmutes_to_value_ref);
+
+/// The position of an attribute of a call site or function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttributeIndex {
+    /// An attribute of the return value, e.g. `noalias`
+    Return,
+    /// An attribute of the function, e.g. `nounwind`
+    Function,
+    /// An attribute of the parameter with this index, starting at 0
+    Param(u32),
+}
+
+impl From<AttributeIndex> for LLVMAttributeIndex {
+    fn from(index: AttributeIndex) -> Self {
+        match index {
+            AttributeIndex::Return => LLVMAttributeReturnIndex,
+            AttributeIndex::Function => LLVMAttributeFunctionIndex,
+            AttributeIndex::Param(i) => i + 1,
+        }
+    }
+}
+
+// Returns the kind of the enum attribute `name`, like `noinline`
+fn enum_attribute_kind(name: &str) -> u32 {
+    let kind = unsafe { LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len()) };
+    assert!(kind != 0, "unknown attribute `{}`", name);
+    kind
+}
+
+impl CallSite {
+    /// Returns the called function, or the pointer called through.
+    pub fn called_value(&self) -> &Value {
+        unsafe { LLVMGetCalledValue(self.into()).into() }
+    }
+
+    pub fn function_type(&self) -> &types::Function {
+        unsafe { LLVMGetCalledFunctionType(self.into()).into() }
+    }
+
+    /// Returns the calling convention, or `None` if it has no `CallConv`
+    /// variant. It must match the one of the called function.
+    pub fn calling_convention(&self) -> Option<CallConv> {
+        CallConv::from_id(unsafe { LLVMGetInstructionCallConv(self.into()) })
+    }
+
+    pub fn set_calling_convention(&self, cc: CallConv) {
+        unsafe { LLVMSetInstructionCallConv(self.into(), cc.id()) }
+    }
+
+    /// Adds the enum attribute `name`, with `value` for attributes that have
+    /// one like `dereferenceable`, and 0 otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no enum attribute called `name`.
+    pub fn add_enum_attribute(&self, index: AttributeIndex, name: &str, value: u64) {
+        let kind = enum_attribute_kind(name);
+        unsafe {
+            let context = LLVMGetTypeContext(LLVMTypeOf(self.into()));
+            let attribute = LLVMCreateEnumAttribute(context, kind, value);
+            LLVMAddCallSiteAttribute(self.into(), index.into(), attribute);
+        }
+    }
+
+    /// Returns the value of the enum attribute `name`, or `None` if it
+    /// isn't set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no enum attribute called `name`.
+    pub fn enum_attribute(&self, index: AttributeIndex, name: &str) -> Option<u64> {
+        let kind = enum_attribute_kind(name);
+        unsafe {
+            let attribute = LLVMGetCallSiteEnumAttribute(self.into(), index.into(), kind);
+            if attribute.is_null() {
+                None
+            } else {
+                Some(LLVMGetEnumAttributeValue(attribute))
+            }
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if there is no enum attribute called `name`.
+    pub fn remove_enum_attribute(&self, index: AttributeIndex, name: &str) {
+        let kind = enum_attribute_kind(name);
+        unsafe { LLVMRemoveCallSiteEnumAttribute(self.into(), index.into(), kind) }
+    }
+
+    /// Adds the string attribute `key`, like `"frame-pointer"="all"`.
+    pub fn add_string_attribute(&self, index: AttributeIndex, key: &str, value: &str) {
+        unsafe {
+            let context = LLVMGetTypeContext(LLVMTypeOf(self.into()));
+            let attribute = LLVMCreateStringAttribute(context,
+                                                      key.as_ptr() as *const _, key.len() as u32,
+                                                      value.as_ptr() as *const _, value.len() as u32);
+            LLVMAddCallSiteAttribute(self.into(), index.into(), attribute);
+        }
+    }
+
+    /// Returns the value of the string attribute `key`, or `None` if it
+    /// isn't set.
+    pub fn string_attribute(&self, index: AttributeIndex, key: &str) -> Option<String> {
         unsafe {
-            LLVMSetValueName(self.into(), c_name.as_ptr());
+            let attribute = LLVMGetCallSiteStringAttribute(self.into(), index.into(),
+                                                           key.as_ptr() as *const _, key.len() as u32);
+            if attribute.is_null() {
+                return None;
+            }
+            let mut len = 0;
+            let value = LLVMGetStringAttributeValue(attribute, &mut len);
+            let bytes = ::std::slice::from_raw_parts(value as *const u8, len as usize);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+
+    pub fn remove_string_attribute(&self, index: AttributeIndex, key: &str) {
+        unsafe {
+            LLVMRemoveCallSiteStringAttribute(self.into(), index.into(),
+                                              key.as_ptr() as *const _, key.len() as u32)
+        }
+    }
+}
+
+/// `call` instructions
+pub struct CallInst(CallSite);
+impl_value!(CallInst: CallSite, call_inst_transmutes_to_value_ref);
+
+/// Whether a `call` is a tail call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TailCallKind {
+    None,
+    /// `tail`: the callee doesn't access the caller's stack, so the call may
+    /// be a tail call
+    Tail,
+    /// `musttail`: the call must be a tail call, which requires it to be
+    /// followed by a `ret` of its result and matching signatures
+    MustTail,
+    /// `notail`: the call must not be a tail call
+    NoTail,
+}
+
+impl CallInst {
+    /// Returns whether this is a `tail` call, whose callee doesn't access the
+    /// caller's stack, so the call may be a tail call. `musttail` calls are
+    /// tail calls too.
+    pub fn is_tail_call(&self) -> bool {
+        unsafe { LLVMIsTailCall(self.into()) != 0 }
+    }
+
+    /// Makes this a `tail` call, or a call that isn't a tail call.
+    pub fn set_tail_call(&self, tail_call: bool) {
+        unsafe { LLVMSetTailCall(self.into(), tail_call as LLVMBool) }
+    }
+}
+
+// The C API of LLVM 14 only knows `tail` calls, see `ffi`
+impl CallInst {
+    pub fn tail_call_kind(&self) -> TailCallKind {
+        match unsafe { ffi::LLVMGetTailCallKind(self.into()) } {
+            0 => TailCallKind::None,
+            1 => TailCallKind::Tail,
+            2 => TailCallKind::MustTail,
+            _ => TailCallKind::NoTail,
+        }
+    }
+
+    pub fn set_tail_call_kind(&self, kind: TailCallKind) {
+        let kind = match kind {
+            TailCallKind::None => 0,
+            TailCallKind::Tail => 1,
+            TailCallKind::MustTail => 2,
+            TailCallKind::NoTail => 3,
+        };
+        unsafe { ffi::LLVMSetTailCallKind(self.into(), kind) }
+    }
+}
+
+/// `alloca` instructions
+pub struct AllocaInst(Instruction);
+impl_value!(AllocaInst: Instruction, alloca_inst_transmutes_to_value_ref);
+
+// Methods shared by the instructions accessing memory. Volatile accesses are
+// never removed or reordered with other volatile accesses, and single thread
+// atomics only synchronize with signal handlers of the same thread.
+macro_rules! impl_memory_access {
+    ($t: ident) => {
+        impl $t {
+            pub fn is_volatile(&self) -> bool {
+                unsafe { LLVMGetVolatile(self.into()) != 0 }
+            }
+
+            pub fn set_volatile(&self, volatile: bool) {
+                unsafe { LLVMSetVolatile(self.into(), volatile as LLVMBool) }
+            }
+
+            pub fn is_single_thread(&self) -> bool {
+                unsafe { LLVMIsAtomicSingleThread(self.into()) != 0 }
+            }
+
+            /// Sets the sync scope to `singlethread` if `single_thread` is
+            /// true, and to the default system scope otherwise.
+            pub fn set_single_thread(&self, single_thread: bool) {
+                unsafe { LLVMSetAtomicSingleThread(self.into(), single_thread as LLVMBool) }
+            }
+        }
+    }
+}
+
+// Methods shared by `load` and `store`, which are atomic if their ordering is
+// not `NotAtomic`. Atomic accesses must have an explicit alignment.
+macro_rules! impl_ordered_access {
+    ($t: ident) => {
+        impl_memory_access!($t);
+
+        impl $t {
+            pub fn ordering(&self) -> AtomicOrdering {
+                unsafe { LLVMGetOrdering(self.into()).into() }
+            }
+
+            pub fn set_ordering(&self, ordering: AtomicOrdering) {
+                unsafe { LLVMSetOrdering(self.into(), ordering.into()) }
+            }
+
+            /// Returns the alignment in bytes, or 0 if it is the ABI
+            /// alignment of the type.
+            pub fn alignment(&self) -> u32 {
+                unsafe { LLVMGetAlignment(self.into()) }
+            }
+
+            pub fn set_alignment(&self, bytes: u32) {
+                unsafe { LLVMSetAlignment(self.into(), bytes) }
+            }
+        }
+    }
+}
+
+/// `load` instructions
+pub struct LoadInst(Instruction);
+impl_value!(LoadInst: Instruction, load_inst_transmutes_to_value_ref);
+impl_ordered_access!(LoadInst);
+
+/// `store` instructions
+pub struct StoreInst(Instruction);
+impl_value!(StoreInst: Instruction, store_inst_transmutes_to_value_ref);
+impl_ordered_access!(StoreInst);
+
+/// `atomicrmw` instructions, which atomically replace the value at an address
+/// with the result of an operation on it, and return the old value
+pub struct AtomicRMWInst(Instruction);
+impl_value!(AtomicRMWInst: Instruction, atomic_rmw_inst_transmutes_to_value_ref);
+impl_memory_access!(AtomicRMWInst);
+
+impl AtomicRMWInst {
+    pub fn op(&self) -> AtomicRMWBinOp {
+        unsafe { LLVMGetAtomicRMWBinOp(self.into()).into() }
+    }
+
+    pub fn set_op(&self, op: AtomicRMWBinOp) {
+        unsafe { LLVMSetAtomicRMWBinOp(self.into(), op.into()) }
+    }
+
+    pub fn ordering(&self) -> AtomicOrdering {
+        unsafe { LLVMGetOrdering(self.into()).into() }
+    }
+
+    pub fn set_ordering(&self, ordering: AtomicOrdering) {
+        unsafe { LLVMSetOrdering(self.into(), ordering.into()) }
+    }
+}
+
+/// `cmpxchg` instructions, which atomically replace the value at an address
+/// if it is equal to an expected one
+pub struct AtomicCmpXchgInst(Instruction);
+impl_value!(AtomicCmpXchgInst: Instruction, atomic_cmpxchg_inst_transmutes_to_value_ref);
+impl_memory_access!(AtomicCmpXchgInst);
+
+impl AtomicCmpXchgInst {
+    /// Returns whether this `cmpxchg` may fail even if the values are equal,
+    /// which is cheaper on some targets when it is retried in a loop.
+    pub fn is_weak(&self) -> bool {
+        unsafe { LLVMGetWeak(self.into()) != 0 }
+    }
+
+    pub fn set_weak(&self, weak: bool) {
+        unsafe { LLVMSetWeak(self.into(), weak as LLVMBool) }
+    }
+
+    pub fn success_ordering(&self) -> AtomicOrdering {
+        unsafe { LLVMGetCmpXchgSuccessOrdering(self.into()).into() }
+    }
+
+    pub fn set_success_ordering(&self, ordering: AtomicOrdering) {
+        unsafe { LLVMSetCmpXchgSuccessOrdering(self.into(), ordering.into()) }
+    }
+
+    /// Returns the ordering of the load if the values are not equal, which
+    /// can't be stronger than the success ordering.
+    pub fn failure_ordering(&self) -> AtomicOrdering {
+        unsafe { LLVMGetCmpXchgFailureOrdering(self.into()).into() }
+    }
+
+    pub fn set_failure_ordering(&self, ordering: AtomicOrdering) {
+        unsafe { LLVMSetCmpXchgFailureOrdering(self.into(), ordering.into()) }
+    }
+}
+
+/// `br` instructions, both conditional and unconditional
+pub struct BranchInst(Instruction);
+impl_value!(BranchInst: Instruction, branch_inst_transmutes_to_value_ref);
+
+/// `ret` instructions
+pub struct ReturnInst(Instruction);
+impl_value!(ReturnInst: Instruction, return_inst_transmutes_to_value_ref);
+
+/// `switch` instructions
+pub struct SwitchInst(Instruction);
+impl_value!(SwitchInst: Instruction, switch_inst_transmutes_to_value_ref);
+
+impl SwitchInst {
+    /// Makes this `switch` jump to `dest` if its value is `on`.
+    pub fn add_case(&self, on: &ConstantInt, dest: &BasicBlock) {
+        unsafe { LLVMAddCase(self.into(), on.into(), dest.into()) }
+    }
+
+    /// Returns the block this `switch` jumps to if no case matches.
+    pub fn default_dest(&self) -> &BasicBlock {
+        unsafe { LLVMGetSwitchDefaultDest(self.into()).into() }
+    }
+}
+
+/// `indirectbr` instructions
+pub struct IndirectBrInst(Instruction);
+impl_value!(IndirectBrInst: Instruction, indirect_br_inst_transmutes_to_value_ref);
+
+impl IndirectBrInst {
+    /// Adds `dest` to the possible destinations of this `indirectbr`. Every
+    /// block whose address may be branched to must be added.
+    pub fn add_destination(&self, dest: &BasicBlock) {
+        unsafe { LLVMAddDestination(self.into(), dest.into()) }
+    }
+}
+
+/// `unreachable` instructions
+pub struct UnreachableInst(Instruction);
+impl_value!(UnreachableInst: Instruction, unreachable_inst_transmutes_to_value_ref);
+
+/// `invoke` instructions, calls that continue at their unwind destination if
+/// the callee throws
+pub struct InvokeInst(CallSite);
+impl_value!(InvokeInst: CallSite, invoke_inst_transmutes_to_value_ref);
+
+impl InvokeInst {
+    /// Returns the block execution continues at if the callee returns.
+    pub fn normal_dest(&self) -> &BasicBlock {
+        unsafe { LLVMGetNormalDest(self.into()).into() }
+    }
+
+    pub fn set_normal_dest(&self, dest: &BasicBlock) {
+        unsafe { LLVMSetNormalDest(self.into(), dest.into()) }
+    }
+
+    /// Returns the block execution continues at if the callee throws. It
+    /// starts with a `landingpad`, `catchswitch` or `cleanuppad`.
+    pub fn unwind_dest(&self) -> &BasicBlock {
+        unsafe { LLVMGetUnwindDest(self.into()).into() }
+    }
+
+    pub fn set_unwind_dest(&self, dest: &BasicBlock) {
+        unsafe { LLVMSetUnwindDest(self.into(), dest.into()) }
+    }
+}
+
+/// `landingpad` instructions, the start of an Itanium style exception
+/// handler
+pub struct LandingPadInst(Instruction);
+impl_value!(LandingPadInst: Instruction, landing_pad_inst_transmutes_to_value_ref);
+
+impl LandingPadInst {
+    /// Adds a clause, which is a `catch` clause if `clause` is a type info
+    /// pointer, and a `filter` clause if it is an array of them.
+    pub fn add_clause(&self, clause: &Constant) {
+        unsafe { LLVMAddClause(self.into(), clause.into()) }
+    }
+
+    pub fn count_clauses(&self) -> u32 {
+        unsafe { LLVMGetNumClauses(self.into()) }
+    }
+
+    /// Returns the clause at `index`.
+    pub fn get_clause(&self, index: u32) -> Option<&Constant> {
+        if index >= self.count_clauses() {
+            None
+        } else {
+            unsafe { Some(LLVMGetClause(self.into(), index).into()) }
         }
     }
 
-    // TODO: This &mut is unecessary
-    pub fn name(&mut self) -> String {
+    /// Returns whether this landing pad is entered even if no clause
+    /// matches, to run cleanups before unwinding further.
+    pub fn is_cleanup(&self) -> bool {
+        unsafe { LLVMIsCleanup(self.into()) != 0 }
+    }
+
+    pub fn set_cleanup(&self, cleanup: bool) {
+        unsafe { LLVMSetCleanup(self.into(), cleanup as LLVMBool) }
+    }
+}
+
+/// `resume` instructions, which continue unwinding an exception caught by a
+/// `landingpad`
+pub struct ResumeInst(Instruction);
+impl_value!(ResumeInst: Instruction, resume_inst_transmutes_to_value_ref);
+
+/// `catchswitch` instructions, which pick the funclet handling an exception
+pub struct CatchSwitchInst(Instruction);
+impl_value!(CatchSwitchInst: Instruction, catch_switch_inst_transmutes_to_value_ref);
+
+impl CatchSwitchInst {
+    /// Adds `handler`, a block starting with a `catchpad` in this
+    /// `catchswitch`.
+    pub fn add_handler(&self, handler: &BasicBlock) {
+        unsafe { LLVMAddHandler(self.into(), handler.into()) }
+    }
+
+    /// Returns the handlers, in the order they are tried.
+    pub fn handlers(&self) -> Vec<&BasicBlock> {
         unsafe {
-            let c_str = LLVMGetValueName(self.into());
-            let len = libc::strlen(c_str);
-            String::from_raw_parts(c_str as *mut u8, len + 1, len + 1)
+            let count = LLVMGetNumHandlers(self.into());
+            let mut handlers = Vec::with_capacity(count as usize);
+            LLVMGetHandlers(self.into(), handlers.as_mut_ptr());
+            handlers.set_len(count as usize);
+            handlers.into_iter().map(|bb| bb.into()).collect()
+        }
+    }
+}
+
+/// The common parent of `catchpad` and `cleanuppad` instructions, which start
+/// a funclet
+pub struct FuncletPadInst(Instruction);
+impl_value!(FuncletPadInst: Instruction, funclet_pad_inst_transmutes_to_value_ref);
+
+impl FuncletPadInst {
+    pub fn count_args(&self) -> u32 {
+        unsafe { LLVMGetNumArgOperands(self.into()) }
+    }
+
+    /// Returns the argument at `index`, which is passed to the personality
+    /// function.
+    pub fn get_arg(&self, index: u32) -> Option<&Value> {
+        if index >= self.count_args() {
+            None
+        } else {
+            unsafe { Some(LLVMGetArgOperand(self.into(), index).into()) }
         }
     }
 }
 
+/// `catchpad` instructions
+pub struct CatchPadInst(FuncletPadInst);
+impl_value!(CatchPadInst: FuncletPadInst, catch_pad_inst_transmutes_to_value_ref);
+
+impl CatchPadInst {
+    /// Returns the `catchswitch` this is a handler of.
+    pub fn catch_switch(&self) -> &CatchSwitchInst {
+        unsafe { LLVMGetParentCatchSwitch(self.into()).into() }
+    }
+}
+
+/// `cleanuppad` instructions
+pub struct CleanupPadInst(FuncletPadInst);
+impl_value!(CleanupPadInst: FuncletPadInst, cleanup_pad_inst_transmutes_to_value_ref);
+
+/// `catchret` instructions, which leave a `catchpad` funclet
+pub struct CatchReturnInst(Instruction);
+impl_value!(CatchReturnInst: Instruction, catch_return_inst_transmutes_to_value_ref);
+
+/// `cleanupret` instructions, which leave a `cleanuppad` funclet
+pub struct CleanupReturnInst(Instruction);
+impl_value!(CleanupReturnInst: Instruction, cleanup_return_inst_transmutes_to_value_ref);
+
 
 #[cfg(test)]
 mod tests {
     use super::Context;
-    use value::{Value, IntoConstValue};
+    use value::{Value, IntoConstValue, Kind};
 
     #[test]
     fn can_set_and_get_value_name() {
         let value_name = "test_value_name";
-        let context = Context::default();
-        let value: &mut Value = 30u32.gen_const(&context).into();
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.void_type(), &[context.i32_type()], false);
+        let mut func = module.add_function(func_ty, "test");
+        let bb = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(bb);
+        let value: &Value = builder.build_alloca(context.i32_type(), "");
 
         value.set_name(value_name);
         assert_eq!(value.name(), value_name);
     }
+
+    #[test]
+    fn can_downcast_values() {
+        let context = Context::new();
+        let value: &Value = 7i32.gen_const(&context);
+
+        match value.downcast() {
+            Kind::ConstantInt(c) => assert_eq!(c.sext_value(), 7),
+            k => panic!("unexpected kind {:?}", k),
+        }
+        assert!(value.try_as_constant().is_some());
+        assert!(value.try_as_instruction().is_none());
+    }
+
+    #[test]
+    fn can_add_incoming_to_phi() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.i32_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        let exit = context.append_basic_block(&mut func, "exit");
+
+        builder.position_at_end(entry);
+        builder.build_br(exit);
+        builder.position_at_end(exit);
+        let phi = builder.build_phi(context.i32_type(), "phi");
+        phi.add_incoming(&[(context.cons(1i32), entry)]);
+
+        assert_eq!(phi.count_incoming(), 1);
+        assert_eq!(phi.get_incoming(0).unwrap().1, entry);
+        assert!(phi.get_incoming(1).is_none());
+        assert!(phi.try_as_instruction().is_some());
+    }
+
+    #[test]
+    fn can_build_switches() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.i32_type(), &[context.i32_type()], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        let one = context.append_basic_block(&mut func, "one");
+        let two = context.append_basic_block(&mut func, "two");
+        let other = context.append_basic_block(&mut func, "other");
+
+        builder.position_at_end(entry);
+        let x = func.get_param(0).unwrap();
+        let switch = builder.build_switch(x, other, &[(1i32.gen_const(&context), one)]);
+        switch.add_case(2i32.gen_const(&context), two);
+        assert_eq!(switch.default_dest(), other);
+        match switch.downcast() {
+            Kind::SwitchInst(_) => {}
+            k => panic!("unexpected kind {:?}", k),
+        }
+
+        for &(bb, result) in &[(one, 10i32), (two, 20)] {
+            builder.position_at_end(bb);
+            builder.build_ret(context.cons(result));
+        }
+        builder.position_at_end(other);
+        assert!(builder.build_unreachable().try_as_unreachable_inst().is_some());
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("switch i32 %0, label %other [\n    i32 1, label %one\n    i32 2, label %two\n  ]"),
+                "{}", ir);
+    }
+
+    #[test]
+    fn can_branch_to_block_addresses() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        let target = context.append_basic_block(&mut func, "target");
+
+        builder.position_at_end(entry);
+        let address = target.address().unwrap();
+        assert!(address.try_as_block_address().is_some());
+        let indirect_br = builder.build_indirect_br(address, &[]);
+        indirect_br.add_destination(target);
+        builder.position_at_end(target);
+        builder.build_ret_void();
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("indirectbr i8* blockaddress(@test, %target), [label %target]"), "{}", ir);
+    }
+
+    #[test]
+    fn entry_blocks_have_no_address() {
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        assert!(entry.address().is_none());
+        let other = context.append_basic_block(&mut func, "other");
+        assert!(other.address().is_some());
+    }
+
+    #[test]
+    fn can_build_atomic_accesses() {
+        use {AtomicOrdering, AtomicRMWBinOp};
+
+        let context = Context::new();
+        let module = context.module_create_with_name("test");
+        let mut builder = context.create_builder();
+
+        let func_ty = ::types::Function::new(context.i1_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        let entry = context.append_basic_block(&mut func, "entry");
+        builder.position_at_end(entry);
+
+        let counter = builder.build_alloca(context.i32_type(), "counter");
+        let store = builder.build_store(context.cons(0i32), counter);
+        store.set_ordering(AtomicOrdering::Release);
+        store.set_alignment(4);
+        assert_eq!(store.ordering(), AtomicOrdering::Release);
+        assert_eq!(store.alignment(), 4);
+
+        let load = builder.build_load(context.i32_type(), counter, "value");
+        load.set_volatile(true);
+        assert!(load.is_volatile());
+        assert_eq!(load.ordering(), AtomicOrdering::NotAtomic);
+
+        let rmw = builder.build_atomic_rmw(AtomicRMWBinOp::Add, counter, load,
+                                           AtomicOrdering::Monotonic, true);
+        assert_eq!(rmw.op(), AtomicRMWBinOp::Add);
+        assert!(rmw.is_single_thread());
+        rmw.set_single_thread(false);
+
+        let cmpxchg = builder.build_atomic_cmpxchg(counter, rmw, context.cons(1i32),
+                                                   AtomicOrdering::AcquireRelease,
+                                                   AtomicOrdering::Acquire, false);
+        cmpxchg.set_weak(true);
+        assert_eq!(cmpxchg.success_ordering(), AtomicOrdering::AcquireRelease);
+        assert_eq!(cmpxchg.failure_ordering(), AtomicOrdering::Acquire);
+        match cmpxchg.downcast() {
+            Kind::AtomicCmpXchgInst(_) => {}
+            k => panic!("unexpected kind {:?}", k),
+        }
+        let success = builder.build_extract_value(cmpxchg, 1, "success");
+        builder.build_ret(success);
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("store atomic i32 0, i32* %counter release, align 4"), "{}", ir);
+        assert!(ir.contains("%value = load volatile i32, i32* %counter"), "{}", ir);
+        assert!(ir.contains("atomicrmw add i32* %counter, i32 %value monotonic"), "{}", ir);
+        assert!(ir.contains("cmpxchg weak i32* %counter, i32 %0, i32 1 acq_rel acquire"), "{}", ir);
+    }
+
+    const EH_DECLARATIONS: &str = "
+        @typeinfo = external constant i8
+        declare void @may_throw()
+        declare void @takes_exception({ i8*, i32 })
+        declare i32 @__gxx_personality_v0(...)
+        declare i32 @__CxxFrameHandler3(...)
+    ";
+
+    #[test]
+    fn can_build_landing_pads() {
+        let context = Context::new();
+        let module = context.parse_ir(EH_DECLARATIONS).unwrap();
+        let mut builder = context.create_builder();
+        let may_throw = module.get_named_function("may_throw").unwrap();
+        let exception_ty = module.get_named_function("takes_exception").unwrap()
+            .get_param(0).unwrap().get_type();
+        let typeinfo = module.get_named_global("typeinfo").unwrap();
+
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        assert!(func.personality().is_none());
+        func.set_personality(module.get_named_function("__gxx_personality_v0").unwrap());
+        assert!(func.personality().is_some());
+        let entry = context.append_basic_block(&mut func, "entry");
+        let exit = context.append_basic_block(&mut func, "exit");
+        let lpad = context.append_basic_block(&mut func, "lpad");
+
+        builder.position_at_end(entry);
+        let invoke = builder.build_invoke(may_throw, &[], exit, lpad, "");
+        assert_eq!(invoke.normal_dest(), exit);
+        assert_eq!(invoke.unwind_dest(), lpad);
+        builder.position_at_end(exit);
+        builder.build_ret_void();
+
+        builder.position_at_end(lpad);
+        let landing_pad = builder.build_landing_pad(exception_ty, &[typeinfo], false, "exn");
+        assert!(!landing_pad.is_cleanup());
+        landing_pad.set_cleanup(true);
+        assert_eq!(landing_pad.count_clauses(), 1);
+        assert!(landing_pad.get_clause(1).is_none());
+        assert!(builder.build_resume(landing_pad).try_as_resume_inst().is_some());
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("personality i32 (...)* @__gxx_personality_v0"), "{}", ir);
+        assert!(ir.contains("invoke void @may_throw()\n          to label %exit unwind label %lpad"), "{}", ir);
+        assert!(ir.contains("%exn = landingpad { i8*, i32 }\n          cleanup\n          catch i8* @typeinfo"),
+                "{}", ir);
+        assert!(ir.contains("resume { i8*, i32 } %exn"), "{}", ir);
+    }
+
+    #[test]
+    fn can_build_funclets() {
+        let context = Context::new();
+        let module = context.parse_ir(EH_DECLARATIONS).unwrap();
+        let mut builder = context.create_builder();
+        let may_throw = module.get_named_function("may_throw").unwrap();
+        let typeinfo = module.get_named_global("typeinfo").unwrap();
+
+        let func_ty = ::types::Function::new(context.void_type(), &[], false);
+        let mut func = module.add_function(func_ty, "test");
+        func.set_personality(module.get_named_function("__CxxFrameHandler3").unwrap());
+        let entry = context.append_basic_block(&mut func, "entry");
+        let cont = context.append_basic_block(&mut func, "cont");
+        let exit = context.append_basic_block(&mut func, "exit");
+        let dispatch = context.append_basic_block(&mut func, "dispatch");
+        let handler = context.append_basic_block(&mut func, "handler");
+        let cleanup = context.append_basic_block(&mut func, "cleanup");
+
+        builder.position_at_end(entry);
+        builder.build_invoke(may_throw, &[], cont, dispatch, "");
+        builder.position_at_end(cont);
+        builder.build_invoke(may_throw, &[], exit, cleanup, "");
+        builder.position_at_end(exit);
+        builder.build_ret_void();
+
+        builder.position_at_end(dispatch);
+        let catch_switch = builder.build_catch_switch(None, None, &[], "cs");
+        catch_switch.add_handler(handler);
+        assert_eq!(catch_switch.handlers(), vec![handler]);
+
+        builder.position_at_end(handler);
+        let catch_pad = builder.build_catch_pad(catch_switch,
+                                                &[typeinfo, context.cons(64i32), typeinfo], "cp");
+        assert_eq!(catch_pad.catch_switch(), catch_switch);
+        assert_eq!(catch_pad.count_args(), 3);
+        assert!(catch_pad.get_arg(3).is_none());
+        builder.build_catch_ret(catch_pad, cont);
+
+        builder.position_at_end(cleanup);
+        let cleanup_pad = builder.build_cleanup_pad(None, &[], "cl");
+        match builder.build_cleanup_ret(cleanup_pad, None).downcast() {
+            Kind::CleanupReturnInst(_) => {}
+            k => panic!("unexpected kind {:?}", k),
+        }
+
+        assert!(module.verify().is_ok());
+        let ir = module.to_string();
+        assert!(ir.contains("%cs = catchswitch within none [label %handler] unwind to caller"), "{}", ir);
+        assert!(ir.contains("%cp = catchpad within %cs [i8* @typeinfo, i32 64, i8* @typeinfo]"), "{}", ir);
+        assert!(ir.contains("catchret from %cp to label %cont"), "{}", ir);
+        assert!(ir.contains("%cl = cleanuppad within none []"), "{}", ir);
+        assert!(ir.contains("cleanupret from %cl unwind to caller"), "{}", ir);
+    }
 }
 
 
@@ -50,7 +1633,9 @@ mod tests {
 
 /// Represents a type that can be inserted as a const in a context
 pub trait IntoConstValue: ContextType {
-    fn gen_const(self, context: &Context) -> LLVMValueRef;
+    type ConstType;
+
+    fn gen_const(self, context: &ContextRef) -> &Self::ConstType;
 }
 
 macro_rules! impl_const_value {
@@ -58,27 +1643,33 @@ macro_rules! impl_const_value {
     // the macro to fail, we should file a bug report
     (UINT: $t: tt) => {
         impl IntoConstValue for $t {
-            fn gen_const(self, context: &Context) -> LLVMValueRef {
+            type ConstType = ConstantInt;
+
+            fn gen_const(self, context: &ContextRef) -> &ConstantInt {
                 unsafe {
-                    LLVMConstInt($t::get_type_in_context(context).into(), self.into(), 0)
+                    LLVMConstInt($t::get_type_in_context(context).into(), self.into(), 0).into()
                 }
             }
         }
     };
     (INT: $t: tt) => {
         impl IntoConstValue for $t {
-            fn gen_const(self, context: &Context) -> LLVMValueRef {
+            type ConstType = ConstantInt;
+
+            fn gen_const(self, context: &ContextRef) -> &ConstantInt {
                 unsafe {
-                    LLVMConstInt($t::get_type_in_context(context).into(), self as u64, 1)
+                    LLVMConstInt($t::get_type_in_context(context).into(), self as u64, 1).into()
                 }
             }
         }
     };
     (FLOAT: $t: tt) => {
         impl IntoConstValue for $t {
-            fn gen_const(self, context: &Context) -> LLVMValueRef {
+            type ConstType = ConstantFP;
+
+            fn gen_const(self, context: &ContextRef) -> &ConstantFP {
                 unsafe {
-                    LLVMConstReal($t::get_type_in_context(context).into(), self.into())
+                    LLVMConstReal($t::get_type_in_context(context).into(), self.into()).into()
                 }
             }
         }